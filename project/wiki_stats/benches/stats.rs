@@ -0,0 +1,179 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use std::env;
+use wiki_stats::output::OutputTarget;
+use wiki_stats::{
+    process_file, split_words, write_stats, AnalysisOptions, OutputFormat, RunInfo, Section,
+    StatsPackage, WordsFrequencyMap,
+};
+
+// Size of the synthetic corpus, in MB of article text: 4 by default, e.g.
+// WIKI_STATS_BENCH_MB=64 cargo bench --bench stats
+const DEFAULT_SIZE_MB: usize = 4;
+const VOCABULARY: usize = 100_000;
+const ARTICLE_SIZE: usize = 4 << 10;
+
+const FORMATS: &[(&str, OutputFormat, &str)] = &[
+    ("json", OutputFormat::Json, "txt"),
+    ("plain", OutputFormat::Plain, "txt"),
+    ("index", OutputFormat::Index, "idx"),
+    ("sqlite", OutputFormat::Sqlite, "db"),
+    ("tsv", OutputFormat::Tsv, "tsv"),
+];
+const SECTIONS: &[Section] = &[
+    Section::Words,
+    Section::Lower,
+    Section::LongestArticle,
+    Section::LongestTitle,
+];
+
+struct Corpus {
+    // All the texts, one after the other.
+    text: String,
+    // The articles as a dataset's .json file, in two halves to be merged.
+    halves: [String; 2],
+}
+
+// Words drawn with a skewed (roughly Zipfian) distribution, some capitalized, with some
+// punctuation, so that the maps see both very frequent & rare words.
+fn corpus() -> Corpus {
+    let size = env::var("WIKI_STATS_BENCH_MB")
+        .ok()
+        .and_then(|mb| mb.parse().ok())
+        .unwrap_or(DEFAULT_SIZE_MB)
+        << 20;
+    let mut state: u64 = 42;
+    let mut next = move |bound: usize| {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (state >> 33) as usize % bound
+    };
+    let mut text = String::with_capacity(size);
+    let mut articles: Vec<serde_json::Value> = vec![];
+    while text.len() < size {
+        let mut article = String::new();
+        while article.len() < ARTICLE_SIZE {
+            let rank = (next(1 << 16) as f64 / (1 << 16) as f64).powi(4) * VOCABULARY as f64;
+            if next(10) == 0 {
+                article.push_str(&format!("Word{}, ", rank as usize));
+            } else {
+                article.push_str(&format!("word{} ", rank as usize));
+            }
+        }
+        text.push_str(&article);
+        articles.push(serde_json::json!({
+            "id": articles.len().to_string(),
+            "title": format!("Title {}", next(VOCABULARY)),
+            "text": article,
+        }));
+    }
+    let second_half = articles.split_off(articles.len() / 2);
+    Corpus {
+        text,
+        halves: [
+            serde_json::to_string(&articles).unwrap(),
+            serde_json::to_string(&second_half).unwrap(),
+        ],
+    }
+}
+
+fn analyze(data: &str) -> StatsPackage {
+    process_file(
+        data.to_string(),
+        "bench.json".to_string(),
+        0,
+        AnalysisOptions::default(),
+    )
+    .unwrap()
+}
+
+fn count_words(text: &str) -> (WordsFrequencyMap, WordsFrequencyMap) {
+    let mut words = WordsFrequencyMap::new();
+    let mut low_words = WordsFrequencyMap::new();
+    WordsFrequencyMap::map_words(Some(&mut words), Some(&mut low_words), text);
+    (words, low_words)
+}
+
+fn tokenization(c: &mut Criterion, corpus: &Corpus) {
+    let mut group = c.benchmark_group("tokenization");
+    group
+        .sample_size(10)
+        .throughput(Throughput::Bytes(corpus.text.len() as u64));
+    group.bench_function("split_words", |b| {
+        b.iter(|| split_words(&corpus.text).count())
+    });
+    group.bench_function("map_words", |b| b.iter(|| count_words(&corpus.text)));
+    // Parsing the JSON & counting the words of both lists & the longest items.
+    group.bench_function("process_file", |b| {
+        b.iter(|| (analyze(&corpus.halves[0]), analyze(&corpus.halves[1])))
+    });
+    group.finish();
+}
+
+fn merge(c: &mut Criterion, corpus: &Corpus) {
+    let mut group = c.benchmark_group("merge");
+    group.sample_size(10);
+    let (middle, _) = corpus
+        .text
+        .char_indices()
+        .find(|&(i, _)| i >= corpus.text.len() / 2)
+        .unwrap();
+    let (first_half, second_half) = corpus.text.split_at(middle);
+    group.bench_function("words", |b| {
+        b.iter_batched(
+            || (count_words(first_half).0, count_words(second_half).0),
+            |(mut words, other_words)| words.merge_with(other_words),
+            BatchSize::PerIteration,
+        )
+    });
+    group.bench_function("stats", |b| {
+        b.iter_batched(
+            || (analyze(&corpus.halves[0]), analyze(&corpus.halves[1])),
+            |(mut stats, other_stats)| stats.merge_with(other_stats),
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+fn output(c: &mut Criterion, corpus: &Corpus) {
+    let dir = tempfile::tempdir().unwrap();
+    let mut group = c.benchmark_group("output");
+    group.sample_size(10);
+    for &(name, format, extension) in FORMATS {
+        let target = OutputTarget {
+            path: dir
+                .path()
+                .join(format!("stats.{}", extension))
+                .to_string_lossy()
+                .to_string(),
+            no_clobber: false,
+            compression: None,
+        };
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter_batched(
+                || {
+                    let mut stats = analyze(&corpus.halves[0]);
+                    stats.merge_with(analyze(&corpus.halves[1]));
+                    stats.compute_distribution(false).unwrap();
+                    stats
+                },
+                |mut stats| {
+                    write_stats(&mut stats, format, SECTIONS, &RunInfo::default(), &target).unwrap()
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn benches(c: &mut Criterion) {
+    let corpus = corpus();
+    tokenization(c, &corpus);
+    merge(c, &corpus);
+    output(c, &corpus);
+}
+
+criterion_group!(stats, benches);
+criterion_main!(stats);
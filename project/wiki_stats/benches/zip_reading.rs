@@ -0,0 +1,100 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+// Uncompressed size of the synthetic archive, in MB. 1 GB by default, e.g.
+// WIKI_STATS_BENCH_MB=100 cargo bench --bench zip_reading
+const DEFAULT_SIZE_MB: usize = 1024;
+const FILE_SIZE: usize = 8 << 20;
+const ARTICLE_SIZE: usize = 4 << 10;
+
+// Deflated .json files of random words (from a 50k words vocabulary), like a dump's shards.
+fn write_dataset(path: &Path, size: usize) {
+    let mut state: u64 = 42;
+    let mut next_word = move || {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        format!("w{}", (state >> 33) % 50_000)
+    };
+    let mut zip = ZipWriter::new(File::create(path).unwrap());
+    let options = FileOptions::default().compression_level(Some(1));
+    for file in 0..size.div_ceil(FILE_SIZE) {
+        let articles: Vec<serde_json::Value> = (0..FILE_SIZE / ARTICLE_SIZE)
+            .map(|article| {
+                let mut text = String::new();
+                while text.len() < ARTICLE_SIZE {
+                    text.push_str(&next_word());
+                    text.push(' ');
+                }
+                serde_json::json!({
+                    "id": article.to_string(),
+                    "title": next_word(),
+                    "text": text,
+                })
+            })
+            .collect();
+        zip.start_file(format!("shards/{:04}.json", file), options)
+            .unwrap();
+        zip.write_all(serde_json::to_string(&articles).unwrap().as_bytes())
+            .unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+// Only the longest article is asked for, so that the words aren't counted and the time is
+// mostly spent reading, decompressing & parsing the entries.
+fn analyze(dataset: &Path, output: &Path, threads: usize) {
+    let status = Command::new(env!("CARGO_BIN_EXE_wiki_stats"))
+        .arg("analyze")
+        .arg("--input")
+        .arg(dataset)
+        .arg("--output")
+        .arg(output)
+        .args(["--format", "tsv", "--sections", "longest-article"])
+        .arg("--threads")
+        .arg(threads.to_string())
+        .env_remove("WIKI_STATS_CONFIG")
+        .stderr(std::process::Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+fn zip_reading(c: &mut Criterion) {
+    let size = env::var("WIKI_STATS_BENCH_MB")
+        .ok()
+        .and_then(|mb| mb.parse().ok())
+        .unwrap_or(DEFAULT_SIZE_MB)
+        << 20;
+    let dir = tempfile::tempdir().unwrap();
+    let dataset = dir.path().join("dataset.zip");
+    write_dataset(&dataset, size);
+    let output = dir.path().join("stats.tsv");
+
+    let mut group = c.benchmark_group("zip_reading");
+    group
+        .sample_size(10)
+        .measurement_time(Duration::from_secs(60))
+        .throughput(Throughput::Bytes(size as u64));
+    let cpus = std::thread::available_parallelism().map_or(1, |cpus| cpus.get());
+    let mut thread_counts = vec![1, cpus];
+    thread_counts.dedup();
+    for threads in thread_counts {
+        group.bench_with_input(
+            BenchmarkId::new("threads", threads),
+            &threads,
+            |b, &threads| b.iter(|| analyze(&dataset, &output, threads)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, zip_reading);
+criterion_main!(benches);
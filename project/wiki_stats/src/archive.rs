@@ -0,0 +1,39 @@
+use anyhow::{Context, Result};
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+use zip::read::ZipArchive;
+
+// The archive mapped in memory once. Every worker gets its own clone of the archive (sharing the
+// mapping & the parsed central directory, but with its own position), so that the entries are
+// read & decompressed by the workers in parallel rather than one by one by the main thread.
+#[derive(Clone)]
+pub struct SharedMap(Arc<Mmap>);
+
+impl AsRef<[u8]> for SharedMap {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+pub type SharedArchive = ZipArchive<Cursor<SharedMap>>;
+
+pub fn open(path: &str) -> Result<SharedArchive> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    // Safety: the archive mustn't be modified while it's analyzed (the process would get a
+    // SIGBUS if it were truncated), as for any other reader of the file.
+    let map = unsafe { Mmap::map(&file) }.with_context(|| format!("Failed to map {}", path))?;
+    Ok(ZipArchive::new(Cursor::new(SharedMap(Arc::new(map))))?)
+}
+
+// Doesn't decompress anything.
+pub fn entry_name(archive: &mut SharedArchive, index: usize) -> Result<String> {
+    Ok(archive.by_index_raw(index)?.name().to_string())
+}
+
+pub fn read_entry(archive: &mut SharedArchive, index: usize) -> Result<String> {
+    let mut data = String::new();
+    archive.by_index(index)?.read_to_string(&mut data)?;
+    Ok(data)
+}
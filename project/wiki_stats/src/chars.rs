@@ -0,0 +1,171 @@
+use crate::lang::script_of;
+use serde_derive::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+// Code points of the Basic Multilingual Plane are counted in a flat table, which is much
+// faster than hashing every character; the rare ones above it go to a map.
+const BMP_SIZE: usize = 0x10000;
+// Label of digits, punctuation, whitespace & symbols, which are shared by all scripts.
+const COMMON_SCRIPT: &str = "Common";
+
+pub struct CharStats {
+    bmp: Vec<u64>,
+    supplementary: HashMap<char, u64>,
+    bytes: Vec<u64>,
+    total: u64,
+    control: u64,
+    replacement: u64,
+}
+
+impl Default for CharStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize)]
+pub struct CodePointCount {
+    pub code_point: String,
+    pub character: String,
+    pub appearances: u64,
+}
+
+#[derive(Serialize)]
+pub struct ScriptCount {
+    pub script: String,
+    pub appearances: u64,
+}
+
+#[derive(Serialize)]
+pub struct ByteCount {
+    pub byte: String,
+    pub appearances: u64,
+}
+
+#[derive(Serialize)]
+pub struct CharSummary {
+    pub total_characters: u64,
+    pub distinct_characters: usize,
+    pub control_characters: u64,
+    pub replacement_characters: u64,
+    pub total_bytes: u64,
+    #[serde(skip)]
+    pub scripts: Vec<ScriptCount>,
+    #[serde(skip)]
+    pub code_points: Vec<CodePointCount>,
+    #[serde(skip)]
+    pub bytes: Vec<ByteCount>,
+}
+
+impl CharStats {
+    pub fn new() -> Self {
+        Self {
+            bmp: vec![0; BMP_SIZE],
+            supplementary: HashMap::new(),
+            bytes: vec![0; 256],
+            total: 0,
+            control: 0,
+            replacement: 0,
+        }
+    }
+
+    pub fn map_text(&mut self, s: &str) {
+        for c in s.chars() {
+            match self.bmp.get_mut(c as usize) {
+                Some(count) => *count += 1,
+                None => *self.supplementary.entry(c).or_insert(0) += 1,
+            }
+            if c.is_control() {
+                self.control += 1;
+            }
+            if c == char::REPLACEMENT_CHARACTER {
+                self.replacement += 1;
+            }
+            self.total += 1;
+        }
+        for b in s.bytes() {
+            self.bytes[b as usize] += 1;
+        }
+    }
+
+    pub fn merge_with(&mut self, other: Self) {
+        for (count, other_count) in self.bmp.iter_mut().zip(other.bmp) {
+            *count += other_count;
+        }
+        for (c, other_count) in other.supplementary {
+            *self.supplementary.entry(c).or_insert(0) += other_count;
+        }
+        for (count, other_count) in self.bytes.iter_mut().zip(other.bytes) {
+            *count += other_count;
+        }
+        self.total += other.total;
+        self.control += other.control;
+        self.replacement += other.replacement;
+    }
+
+    // Everything sorted by appearances (most frequent first), ties by code point, script or byte.
+    pub fn summary(&self) -> CharSummary {
+        let mut code_points: Vec<(char, u64)> = self
+            .bmp
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .filter_map(|(code, &count)| Some((char::from_u32(code as u32)?, count)))
+            .chain(self.supplementary.iter().map(|(&c, &count)| (c, count)))
+            .collect();
+        code_points.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let mut scripts: HashMap<String, u64> = HashMap::new();
+        for &(c, count) in &code_points {
+            let script = if !c.is_alphabetic() {
+                COMMON_SCRIPT.to_string()
+            } else {
+                format!("{:?}", script_of(c))
+            };
+            *scripts.entry(script).or_insert(0) += count;
+        }
+        let mut scripts: Vec<ScriptCount> = scripts
+            .into_iter()
+            .map(|(script, appearances)| ScriptCount {
+                script,
+                appearances,
+            })
+            .collect();
+        scripts.sort_unstable_by(|a, b| {
+            b.appearances
+                .cmp(&a.appearances)
+                .then_with(|| a.script.cmp(&b.script))
+        });
+
+        let mut bytes: Vec<ByteCount> = self
+            .bytes
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(byte, &appearances)| ByteCount {
+                byte: format!("0x{:02X}", byte),
+                appearances,
+            })
+            .collect();
+        bytes.sort_by_key(|byte| Reverse(byte.appearances));
+
+        CharSummary {
+            total_characters: self.total,
+            distinct_characters: code_points.len(),
+            control_characters: self.control,
+            replacement_characters: self.replacement,
+            total_bytes: self.bytes.iter().sum(),
+            scripts,
+            code_points: code_points
+                .into_iter()
+                .map(|(c, appearances)| CodePointCount {
+                    code_point: format!("U+{:04X}", c as u32),
+                    character: c.to_string(),
+                    appearances,
+                })
+                .collect(),
+            bytes,
+        }
+    }
+}
@@ -0,0 +1,512 @@
+use crate::compress::Compression;
+use crate::markup::MarkupMode;
+use crate::output::{OutputTarget, STDOUT};
+use crate::{dedup, sketch, OutputFormat, Section};
+use anyhow::Result;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, Args, Parser, Subcommand};
+use std::fs::File;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+pub const ANALYZE_COMMAND: &str = "analyze";
+
+/// Analyzes article datasets stored as .json files within a .zip archive.
+///
+/// Uses one thread per virtual CPU core to count each word's number of appearances (as written &
+/// lowercased), find the longest article & title and describe the distribution of the words
+/// (lengths histogram & Zipf exponent fitted on the frequency list, with its R^2).
+///
+/// Without a command, the options are those of 'analyze'.
+#[derive(Parser)]
+#[command(name = "wiki_stats", version, propagate_version = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten, next_help_heading = "Analyze options (without a command)")]
+    pub analyze: AnalyzeArguments,
+
+    /// TOML file with defaults for the options of all commands [default: wiki_stats.toml, if
+    /// found in the current directory]
+    ///
+    /// Keys are the options' long names, '-' or '_' separated, e.g. 'input = "dump.zip"',
+    /// 'spill-threshold = "2G"', 'sections = ["lower", "words"]', 'quiet = true'; a key applies to
+    /// every command having that option. Not read by 'completions' & 'man'. Every option can also
+    /// be set by a WIKI_STATS_<NAME> environment variable, e.g.
+    /// WIKI_STATS_SPILL_THRESHOLD=2G or WIKI_STATS_METRICS=1. Precedence: command line >
+    /// environment > config file > built-in defaults.
+    #[arg(long, global = true, env = "WIKI_STATS_CONFIG", value_name = "FILE")]
+    pub config: Option<String>,
+
+    /// Logs more: -v adds one line per file processed (name, size, number of articles &
+    /// duration), -vv everything
+    #[arg(long, short, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Only logs warnings & errors
+    #[arg(long, short, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Also writes the logs to the file, as JSON lines (appended), e.g. for a job scheduler
+    ///
+    /// Logs are always written to the standard error, never mixed with results written to the
+    /// standard output.
+    #[arg(long, global = true, env = "WIKI_STATS_LOG_FILE", value_name = "FILE")]
+    pub log_file: Option<PathBuf>,
+}
+
+// Analyze's options given before another command would be silently ignored. Clap's
+// args_conflicts_with_subcommands would also reject the global options, e.g. 'wiki_stats -v merge'.
+pub fn check_default_command_options(command: &mut clap::Command, matches: &ArgMatches) {
+    let Some((name, _)) = matches.subcommand() else {
+        return;
+    };
+    command.build();
+    let option = command
+        .get_arguments()
+        .filter(|arg| !arg.is_global_set())
+        .find(|arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine))
+        .map(ToString::to_string);
+    if let Some(option) = option {
+        command
+            .error(
+                ErrorKind::ArgumentConflict,
+                format!(
+                    "'{}' is an option of 'analyze', the default command, and can't be used with '{}'",
+                    option, name
+                ),
+            )
+            .exit();
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Processes a dataset and writes its stats (the default command)
+    Analyze(AnalyzeArguments),
+    /// Combines previously written outputs (any format) into one, as if their datasets had been
+    /// analyzed together
+    Merge(MergeArguments),
+    /// Analyzes the .zip archives added to a directory, merging each into a cumulative output
+    ///
+    /// The directory is scanned every --interval seconds. The archives already merged are
+    /// listed in a state file, so that a restart doesn't count them twice. Archives which can't
+    /// be opened yet (e.g. still being copied) are retried at the next scan; those which can't be
+    /// analyzed (e.g. a malformed .json entry) are skipped for good and listed as failed in the
+    /// state file. The sections are merged as by 'merge'.
+    Watch(WatchArguments),
+    /// Compares two previously written outputs (any format)
+    ///
+    /// Reports new & vanished words, the words with the largest absolute & relative changes in
+    /// appearances, and changes of the longest article & title.
+    Diff(DiffArguments),
+    /// Answers questions about a previously written index without reprocessing the dataset
+    Query(QueryArguments),
+    /// Serves a previously written output (any format) as a JSON API over HTTP, e.g. for
+    /// dashboards
+    ///
+    /// Endpoints (GET only): '/word/<word>' gives the word's appearances as written & lowercased
+    /// (404 if it never appears); '/top?n=10&lowercase=false' the n most frequent words;
+    /// '/longest' the longest article & title. Errors are JSON objects with an 'error' message.
+    Serve(ServeArguments),
+    /// Inspects the configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Prints a shell completion script
+    ///
+    /// e.g. 'wiki_stats completions bash > /etc/bash_completion.d/wiki_stats'
+    Completions(CompletionsArguments),
+    /// Writes man pages
+    Man(ManArguments),
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Prints the effective value of every option of 'analyze', as a config file, with where
+    /// each value comes from (environment variable, config file or default)
+    Show,
+}
+
+#[derive(Args)]
+pub struct OutputArguments {
+    /// File where the stats are written; '-' writes to the standard output
+    ///
+    /// Must end with the format's extension (.txt, .idx, .db or .tsv), else the default is used,
+    /// optionally followed by '.gz' or '.zst' to compress it. Outputs are written to a temporary
+    /// file next to the output file, synced to disk & renamed into place once complete, so an
+    /// interrupted or failed run never leaves a partial output nor damages a previous one. Status
+    /// messages & metrics are always printed to the standard error.
+    #[arg(long, short, value_name = "FILE")]
+    pub output: Option<String>,
+
+    /// Format of the output file [default: json]
+    #[arg(long, short, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// Same as '--format plain'
+    #[arg(long, short)]
+    pub plain: bool,
+
+    /// Comma separated sections to write, in this order
+    ///
+    /// The sections of options that aren't enabled (e.g. 'chars' without --char-stats) are
+    /// never written. The sections left out aren't computed at all, e.g. '--sections lower'
+    /// needs about half the memory of a full run (the lowercase list is still computed, but not
+    /// written, for 'cooccur' & 'distribution'), and --dedup only runs for 'duplicates' or
+    /// --dedup-exclude. 'tsv' outputs only have the lists & longest items; 'index' outputs keep
+    /// their fixed layout, with empty lists for the ones left out.
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "words,lower,longest-article,longest-title,distribution,approx,languages,cooccur,chars,duplicates"
+    )]
+    pub sections: Vec<Section>,
+
+    /// Refuses to overwrite an existing output file
+    ///
+    /// Checked before processing the dataset, and again when the output is moved into place.
+    #[arg(long)]
+    pub no_clobber: bool,
+
+    /// Compresses the output file while writing it
+    ///
+    /// Also enabled by an --output ending with '.gz' or '.zst'; with --compress the extension is
+    /// appended to the output file name, e.g. 'stats.txt.gz'. Streamed: the uncompressed output
+    /// is never kept whole in memory nor on disk, except for 'sqlite' outputs which are
+    /// compressed after being written. Compressed outputs are read transparently by every
+    /// subcommand reading outputs ('merge', 'query', 'diff', 'serve' & 'watch'), whatever their
+    /// extension.
+    #[arg(long, value_enum)]
+    pub compress: Option<Compression>,
+}
+
+impl OutputArguments {
+    pub fn format(&self) -> OutputFormat {
+        match self.format {
+            Some(format) => format,
+            None if self.plain => OutputFormat::Plain,
+            None => OutputFormat::Json,
+        }
+    }
+
+    pub fn sections(&self) -> Vec<Section> {
+        let mut sections: Vec<Section> = vec![];
+        for section in &self.sections {
+            if !sections.contains(section) {
+                sections.push(*section);
+            }
+        }
+        sections
+    }
+
+    pub fn target(&self) -> OutputTarget {
+        let format = self.format();
+        // The compression extension comes after the format's: 'stats.txt.gz'.
+        let compression = self
+            .compress
+            .or_else(|| self.output.as_deref().and_then(Compression::from_path));
+        let output_path = self
+            .output
+            .as_deref()
+            .map(|s| Compression::from_path(s).map_or(s, |c| &s[..s.len() - c.extension().len()]));
+        OutputTarget {
+            path: match output_path {
+                Some(s) if s.ends_with(format.extension()) || s == STDOUT => s.to_string(),
+                _ => format.default_output().to_string(),
+            } + match output_path {
+                Some(STDOUT) => "",
+                _ => compression.map_or("", Compression::extension),
+            },
+            no_clobber: self.no_clobber,
+            compression,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct AnalyzeArguments {
+    /// The dataset to analyze; must be a .zip archive [default: datasets/dataset.zip]
+    #[arg(long, short, value_name = "FILE")]
+    pub input: Option<String>,
+
+    #[command(flatten)]
+    pub output: OutputArguments,
+
+    /// Prints the progress (files processed / total files, all files counted even if not .json)
+    /// and the time taken by the processing & the writing of the output
+    #[arg(long, short)]
+    pub metrics: bool,
+
+    /// Number of files processed at the same time [default: the number of CPUs]
+    ///
+    /// The output is the same whatever the number of threads: words are written sorted, ties are
+    /// broken by word or path, duplicates are decided in the order of the archive, and --approx
+    /// counts the files into a fixed number of sketches (8, so it uses at most 8 threads). Except
+    /// for the run info (start date, processing time) of the 'sqlite' output.
+    #[arg(long, short, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub threads: Option<u32>,
+
+    /// Detects the language of each article and writes the number of articles & the frequency
+    /// lists of each language
+    ///
+    /// Uses a bundled trigram & script based classifier, no network needed. Not included in
+    /// 'index' & 'tsv' outputs.
+    #[arg(long, short)]
+    pub languages: bool,
+
+    /// Removes markup from the articles' text before counting words
+    ///
+    /// 'wikitext' drops templates, tables, references, comments, file & category links, URLs,
+    /// tags, heading & emphasis marks, keeping the display text of links; 'html' drops tags,
+    /// comments, scripts & styles. Entities (numeric, Latin-1 & punctuation) are decoded in both
+    /// cases, unknown ones dropped. The size of the longest article is measured after stripping.
+    #[arg(long, value_enum, default_value_t = MarkupMode::None)]
+    pub strip_markup: MarkupMode,
+
+    /// Counts words approximately, in bounded memory, for vocabularies that don't fit in RAM
+    ///
+    /// Only the most frequent words are written (with their estimated appearances), followed by
+    /// the estimates' error bounds, the total number of words and an estimate of the number of
+    /// distinct words. Uses 8 sets of a Count-Min sketch, Space-Saving heavy hitters &
+    /// HyperLogLog, each counting every 8th file, merged at the end (and so at most 8 threads).
+    /// The distribution of the words isn't computed.
+    #[arg(long, conflicts_with = "languages")]
+    pub approx: bool,
+
+    /// Number of most frequent words reported by --approx
+    #[arg(long, requires = "approx", default_value_t = 1000)]
+    pub top_k: usize,
+
+    /// Memory shared by the 8 sets of sketches, e.g. 512M, 2G
+    #[arg(long, requires = "approx", default_value = "1G", value_parser = sketch::parse_memory_size)]
+    pub memory_limit: usize,
+
+    /// Counts words exactly even when the vocabulary doesn't fit in RAM
+    ///
+    /// Once the words of a worker (or of the merged results) outgrow their share of the
+    /// threshold, they are written to disk as alphabetically sorted runs; at the end all runs are
+    /// merged (k-way) while writing the output, so spilled words are written sorted.
+    #[arg(long, conflicts_with_all = ["approx", "languages"])]
+    pub spill: bool,
+
+    /// Estimated memory of the words kept in RAM by all workers before spilling, e.g. 512M, 2G
+    #[arg(long, requires = "spill", default_value = "1G", value_parser = sketch::parse_memory_size)]
+    pub spill_threshold: usize,
+
+    /// Where the runs are written, in a 'wiki_stats-spill-<pid>' directory removed at exit
+    /// [default: the system's temporary directory]
+    #[arg(long, requires = "spill", value_name = "DIR")]
+    pub spill_dir: Option<PathBuf>,
+
+    /// Counts the words appearing near each target word of the file (one per line, matched
+    /// lowercased)
+    ///
+    /// Words at most --window tokens before or after a target, within the same title or text,
+    /// are written in a 'Co-occurrences' section with their PMI score: log2(count * total words
+    /// / (target's appearances * word's appearances * 2 * window)), computed from the lowercase
+    /// frequency list.
+    #[arg(long, conflicts_with_all = ["approx", "spill"], value_name = "FILE")]
+    pub cooccur: Option<String>,
+
+    /// Maximum distance, in tokens, between a target & a co-occurring word
+    #[arg(long, requires = "cooccur", default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    pub window: u32,
+
+    /// Maximum number of co-occurring words written per target, the most frequent first
+    #[arg(long, requires = "cooccur", default_value_t = 100)]
+    pub cooccur_limit: usize,
+
+    /// Writes character statistics of the titles & texts (after --strip-markup), for encoding
+    /// checks
+    ///
+    /// The total number of characters & bytes, the number of distinct characters, of control
+    /// characters & of replacement characters (U+FFFD), followed by the appearances of each
+    /// script (Latin, Cyrillic, Han, ...; digits, punctuation, whitespace & symbols count as
+    /// 'Common'), of each Unicode code point & of each byte of the UTF-8 text, all sorted by
+    /// number of appearances.
+    #[arg(long)]
+    pub char_stats: bool,
+
+    /// Detects duplicate articles, e.g. the same article under several ids or paths
+    ///
+    /// Exact duplicates are found by hashing their text (after --strip-markup), near-duplicates
+    /// by comparing MinHash signatures of their 3-word shingles (locality-sensitive hashing).
+    /// The first article with some content (in the order of the archive) is kept, the later ones
+    /// are its duplicates.
+    /// Writes the number of duplicates & the clusters of duplicates, as 'file/id' paths with
+    /// their estimated similarity (Jaccard) to the kept article. Articles without words are not
+    /// checked.
+    #[arg(long)]
+    pub dedup: bool,
+
+    /// Minimum similarity of near-duplicates, between 0 and 1
+    #[arg(long, requires = "dedup", default_value_t = 0.8, value_parser = dedup::parse_threshold)]
+    pub dedup_threshold: f64,
+
+    /// Leaves duplicates out of all counts (words, longest items, languages, ...)
+    #[arg(long, requires = "dedup")]
+    pub dedup_exclude: bool,
+
+    /// Writes one CSV row per article to the file: its 'file/id' path, title, size of its text
+    /// in bytes & in characters, number of tokens, of distinct tokens (case-sensitive) and
+    /// average length of its tokens in characters
+    ///
+    /// Measured after --strip-markup; duplicates left out by --dedup-exclude have no row. Rows
+    /// are streamed to a temporary file by each worker and appended in the order of the archive,
+    /// so the articles are never all kept in memory. '-' writes to the standard output; a '.gz'
+    /// or '.zst' extension compresses the file. --no-clobber applies to it too.
+    #[arg(long, value_name = "FILE")]
+    pub per_article: Option<String>,
+}
+
+#[derive(Args)]
+pub struct MergeArguments {
+    /// Outputs to merge
+    #[arg(required = true, num_args = 1.., value_name = "STATS")]
+    pub inputs: Vec<String>,
+
+    #[command(flatten)]
+    pub output: OutputArguments,
+}
+
+#[derive(Args)]
+pub struct WatchArguments {
+    /// Directory where new .zip archives are looked for (not recursively)
+    #[arg(value_name = "DIR")]
+    pub dir: PathBuf,
+
+    #[command(flatten)]
+    pub output: OutputArguments,
+
+    /// File listing the archives already merged into the output, & those skipped [default: the output file
+    /// name followed by '.watch.json']
+    #[arg(long, value_name = "FILE")]
+    pub state: Option<String>,
+
+    /// Seconds between two scans of the directory
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    pub interval: u64,
+
+    /// Scans the directory once, then exits (e.g. when run periodically by a scheduler)
+    #[arg(long)]
+    pub once: bool,
+
+    /// Number of files processed at the same time [default: the number of CPUs]
+    #[arg(long, short, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub threads: Option<u32>,
+
+    /// Detects the language of each article (see 'analyze --help'); not with 'index' & 'tsv' outputs,
+    /// which don't keep the languages read back at restart
+    #[arg(long, short)]
+    pub languages: bool,
+
+    /// Removes markup from the articles' text before counting words (see 'analyze --help')
+    #[arg(long, value_enum, default_value_t = MarkupMode::None)]
+    pub strip_markup: MarkupMode,
+}
+
+#[derive(Args)]
+pub struct ServeArguments {
+    /// Output to serve, loaded in memory at start
+    #[arg(long, value_name = "FILE")]
+    pub stats: String,
+
+    /// Port to listen on; 0 lets the system choose one (logged at start)
+    #[arg(long, default_value_t = 8080)]
+    pub port: u16,
+
+    /// Address to listen on, e.g. 0.0.0.0 for all interfaces
+    #[arg(long, default_value = "127.0.0.1")]
+    pub bind: IpAddr,
+}
+
+#[derive(Args)]
+pub struct DiffArguments {
+    pub old: String,
+
+    pub new: String,
+
+    /// Maximum number of words listed per category
+    #[arg(long, short, default_value_t = 20)]
+    pub limit: usize,
+
+    /// Words appearing fewer times in both outputs are left out of the relative changes
+    #[arg(long, short, default_value_t = 10)]
+    pub min_count: u32,
+
+    /// Compares the lowercase frequency lists instead of the as-written ones
+    #[arg(long, short = 'L')]
+    pub lowercase: bool,
+}
+
+#[derive(Args)]
+pub struct QueryArguments {
+    /// Index written with '--format index'
+    pub index: String,
+
+    /// Prints the word's number of appearances and its rank
+    #[arg(long, short)]
+    pub word: Option<String>,
+
+    /// Prints the words starting with the prefix, sorted by number of appearances
+    #[arg(long, short)]
+    pub prefix: Option<String>,
+
+    /// Maximum number of words printed for --prefix
+    #[arg(long, short, default_value_t = 20)]
+    pub limit: usize,
+
+    /// Prints the n most frequent words
+    #[arg(long, short, value_name = "N")]
+    pub top: Option<usize>,
+
+    /// Prints the word ranked n-th by number of appearances
+    #[arg(long, short, value_name = "N")]
+    pub rank: Option<usize>,
+
+    /// Prints the longest article & title
+    #[arg(long)]
+    pub longest: bool,
+
+    /// Searches the lowercase frequency list instead of the as-written one
+    #[arg(long, short = 'L')]
+    pub lowercase: bool,
+}
+
+#[derive(Args)]
+pub struct CompletionsArguments {
+    #[arg(value_enum)]
+    pub shell: clap_complete::Shell,
+}
+
+#[derive(Args)]
+pub struct ManArguments {
+    /// Directory where a page is written for the program & for each subcommand; without it,
+    /// the program's page is printed to the standard output
+    #[arg(long, value_name = "DIR")]
+    pub out_dir: Option<PathBuf>,
+}
+
+// One page for the program & one per subcommand ('wiki_stats-analyze.1', ...), like git's.
+pub fn write_man_pages(mut command: clap::Command, out_dir: Option<&Path>) -> Result<()> {
+    command.build();
+    let Some(out_dir) = out_dir else {
+        clap_mangen::Man::new(command).render(&mut io::stdout())?;
+        return Ok(());
+    };
+    let name = command.get_name().to_string();
+    let mut pages = vec![(name.clone(), command.clone())];
+    for subcommand in command.get_subcommands().filter(|s| s.get_name() != "help") {
+        let page_name = format!("{}-{}", name, subcommand.get_name());
+        pages.push((page_name.clone(), subcommand.clone().name(page_name)));
+    }
+    for (page_name, page_command) in pages {
+        let mut page = File::create(out_dir.join(format!("{}.1", page_name)))?;
+        clap_mangen::Man::new(page_command).render(&mut page)?;
+    }
+    Ok(())
+}
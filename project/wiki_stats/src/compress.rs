@@ -0,0 +1,101 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xB5, 0x2F, 0xFD];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+
+    pub fn from_path(path: &str) -> Option<Self> {
+        [Compression::Gzip, Compression::Zstd]
+            .into_iter()
+            .find(|compression| path.ends_with(compression.extension()))
+    }
+}
+
+// Streams everything written through the encoder, if any, into the writer.
+pub enum Encoder<W: Write> {
+    None(W),
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(writer: W, compression: Option<Compression>) -> Result<Self> {
+        Ok(match compression {
+            None => Encoder::None(writer),
+            Some(Compression::Gzip) => {
+                Encoder::Gzip(GzEncoder::new(writer, flate2::Compression::default()))
+            }
+            Some(Compression::Zstd) => Encoder::Zstd(zstd::Encoder::new(writer, 0)?),
+        })
+    }
+
+    // Writes the end of the compressed stream, returning the underlying writer.
+    pub fn finish(self) -> Result<W> {
+        Ok(match self {
+            Encoder::None(writer) => writer,
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            Encoder::Zstd(encoder) => encoder.finish()?,
+        })
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::None(writer) => writer.write(buf),
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::None(writer) => writer.flush(),
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+// Reads a whole file, decompressing it if it's gzip or zstd compressed (detected from the
+// content, whatever the extension).
+pub fn read_file(path: &str) -> Result<Vec<u8>> {
+    let data = fs::read(path).with_context(|| format!("Failed to read {}", path))?;
+    let mut decompressed = vec![];
+    if data.starts_with(GZIP_MAGIC) {
+        MultiGzDecoder::new(data.as_slice())
+            .read_to_end(&mut decompressed)
+            .with_context(|| format!("Failed to decompress {}", path))?;
+    } else if data.starts_with(ZSTD_MAGIC) {
+        zstd::Decoder::new(data.as_slice())?
+            .read_to_end(&mut decompressed)
+            .with_context(|| format!("Failed to decompress {}", path))?;
+    } else {
+        return Ok(data);
+    }
+    Ok(decompressed)
+}
+
+pub fn is_compressed(path: &str) -> Result<bool> {
+    let mut magic = [0; 4];
+    let read = File::open(path)?.read(&mut magic)?;
+    Ok(magic[..read].starts_with(GZIP_MAGIC) || magic[..read].starts_with(ZSTD_MAGIC))
+}
@@ -0,0 +1,234 @@
+use anyhow::{bail, Context, Result};
+use clap::builder::FalseyValueParser;
+use clap::parser::ValueSource;
+use clap::{ArgAction, Command};
+use std::collections::HashSet;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+
+pub const DEFAULT_CONFIG_FILE: &str = "wiki_stats.toml";
+const ENV_PREFIX: &str = "WIKI_STATS_";
+const CONFIG_ARG: &str = "config";
+// Subcommands without options, which don't read the config file: a broken one mustn't prevent
+// installing the completions or the man pages.
+const COMMANDS_WITHOUT_CONFIG: &[&str] = &["completions", "man"];
+
+fn env_name(id: &str) -> String {
+    format!("{}{}", ENV_PREFIX, id.to_uppercase())
+}
+
+// Config file keys may be written like the options (spill-threshold) or like the fields they
+// set (spill_threshold).
+fn key_to_id(key: &str) -> String {
+    key.replace('-', "_")
+}
+
+fn id_to_key(id: &str) -> String {
+    id.replace('_', "-")
+}
+
+fn to_arg_value(value: &toml::Value) -> Result<String> {
+    Ok(match value {
+        toml::Value::String(s) => s.clone(),
+        toml::Value::Integer(i) => i.to_string(),
+        toml::Value::Float(f) => f.to_string(),
+        toml::Value::Boolean(b) => b.to_string(),
+        // Lists are given as their comma separated form, e.g. sections.
+        toml::Value::Array(values) => values
+            .iter()
+            .map(to_arg_value)
+            .collect::<Result<Vec<_>>>()?
+            .join(","),
+        _ => bail!("Unsupported value in config file: {}", value),
+    })
+}
+
+// Whether an option of the program (i.e. a global one or one of 'analyze', the default command)
+// takes the next argument as its value, e.g. '--log-file x' or '-i x'.
+fn takes_value(command: &Command, option: &str) -> bool {
+    command.get_arguments().any(|arg| {
+        arg.get_action().takes_values()
+            && match option.strip_prefix("--") {
+                Some(long) => arg.get_long() == Some(long),
+                None => option.len() == 2 && arg.get_short() == option[1..].chars().next(),
+            }
+    })
+}
+
+// The --config value & the subcommand, looked up in the raw command line since the config file
+// gives the parser its defaults.
+fn scan_args(command: &Command, args: &[OsString]) -> (Option<String>, Option<String>) {
+    let mut config = None;
+    let mut subcommand = None;
+    let mut args = args.iter().skip(1).map(|arg| arg.to_string_lossy());
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        } else if arg == "--config" {
+            config = args.next().map(|value| value.to_string());
+        } else if let Some(value) = arg.strip_prefix("--config=") {
+            config = Some(value.to_string());
+        } else if subcommand.is_none() && takes_value(command, &arg) {
+            args.next();
+        } else if subcommand.is_none() && !arg.starts_with('-') {
+            subcommand = Some(arg.to_string());
+        }
+    }
+    (config, subcommand)
+}
+
+fn option_ids(command: &Command) -> impl Iterator<Item = &str> {
+    command
+        .get_arguments()
+        .filter(|arg| !arg.is_positional())
+        .map(|arg| arg.get_id().as_str())
+}
+
+pub struct Config {
+    path: Option<String>,
+    values: Vec<(String, String)>,
+    ids: HashSet<String>,
+}
+
+impl Config {
+    pub fn from_args(command: &Command, args: &[OsString]) -> Result<Self> {
+        let (path, subcommand) = scan_args(command, args);
+        match subcommand {
+            Some(subcommand) if COMMANDS_WITHOUT_CONFIG.contains(&subcommand.as_str()) => {
+                Ok(Self::empty())
+            }
+            _ => Self::find(path.as_deref()),
+        }
+    }
+
+    fn empty() -> Self {
+        Self {
+            path: None,
+            values: vec![],
+            ids: HashSet::new(),
+        }
+    }
+
+    // The file given by --config (or WIKI_STATS_CONFIG) must exist, while wiki_stats.toml is
+    // only used if found in the current directory.
+    pub fn find(path: Option<&str>) -> Result<Self> {
+        let path = path
+            .map(str::to_string)
+            .or_else(|| env::var(env_name(CONFIG_ARG)).ok());
+        match path {
+            Some(path) => Self::load(&path),
+            None if Path::new(DEFAULT_CONFIG_FILE).is_file() => Self::load(DEFAULT_CONFIG_FILE),
+            None => Ok(Self::empty()),
+        }
+    }
+
+    fn load(path: &str) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path))?;
+        let table: toml::Table = contents
+            .parse()
+            .with_context(|| format!("Failed to parse config file {}", path))?;
+        let mut values = vec![];
+        for (key, value) in &table {
+            let value = to_arg_value(value)
+                .with_context(|| format!("Invalid value of '{}' in config file {}", key, path))?;
+            values.push((key_to_id(key), value));
+        }
+        Ok(Self {
+            path: Some(path.to_string()),
+            ids: values.iter().map(|(id, _)| id.clone()).collect(),
+            values,
+        })
+    }
+
+    // Gives every option of the program & of its subcommands a WIKI_STATS_* environment
+    // variable, and the config file's values as defaults: a key applies to every subcommand
+    // having that option. Clap then resolves the precedence: command line > environment > config
+    // file > built-in defaults.
+    pub fn apply(&self, command: Command) -> Result<Command> {
+        let ids: HashSet<&str> = option_ids(&command)
+            .chain(command.get_subcommands().flat_map(option_ids))
+            .collect();
+        for (id, _) in &self.values {
+            if id == CONFIG_ARG || !ids.contains(id.as_str()) {
+                bail!(
+                    "Unknown option '{}' in config file {}",
+                    id_to_key(id),
+                    self.path.as_deref().unwrap_or_default()
+                );
+            }
+        }
+        Ok(self
+            .apply_to_options(command)
+            .mut_subcommands(|subcommand| self.apply_to_options(subcommand)))
+    }
+
+    fn apply_to_options(&self, command: Command) -> Command {
+        command.mut_args(|arg| {
+            if arg.is_positional() || arg.get_id() == CONFIG_ARG {
+                return arg;
+            }
+            let id = arg.get_id().to_string();
+            let mut arg = arg.env(env_name(&id));
+            // Lets flags be set from the environment as 1/0, yes/no, on/off...
+            if matches!(arg.get_action(), ArgAction::SetTrue) {
+                arg = arg.value_parser(FalseyValueParser::new());
+            }
+            match self.values.iter().find(|(key, _)| *key == id) {
+                Some((_, value)) => arg.default_value(value.clone()),
+                None => arg,
+            }
+        })
+    }
+
+    // Prints the value of every option of the command as a config file, with where it
+    // comes from (the command line isn't considered).
+    pub fn print_effective(&self, command: &Command) -> Result<()> {
+        let matches = command.clone().try_get_matches_from([command.get_name()])?;
+        match &self.path {
+            Some(path) => println!("# Config file: {}", path),
+            None => println!("# No config file ({} not found)", DEFAULT_CONFIG_FILE),
+        }
+        for arg in command.get_arguments().filter(|arg| !arg.is_positional()) {
+            let id = arg.get_id().as_str();
+            if id == CONFIG_ARG {
+                continue;
+            }
+            let source = match matches.value_source(id) {
+                Some(ValueSource::EnvVariable) => env_name(id),
+                Some(ValueSource::DefaultValue) if self.ids.contains(id) => {
+                    "config file".to_string()
+                }
+                Some(_) => "default".to_string(),
+                None => {
+                    println!("# {} (not set)", id_to_key(id));
+                    continue;
+                }
+            };
+            let raw: Vec<String> = matches
+                .get_raw(id)
+                .into_iter()
+                .flatten()
+                .map(|value| value.to_string_lossy().to_string())
+                .collect();
+            let value = if matches!(arg.get_action(), ArgAction::SetTrue | ArgAction::SetFalse) {
+                toml::Value::Boolean(matches.get_flag(id))
+            } else if arg.get_value_delimiter().is_some() {
+                toml::Value::Array(raw.into_iter().map(toml::Value::String).collect())
+            } else {
+                let value = raw.concat();
+                if let Ok(i) = value.parse() {
+                    toml::Value::Integer(i)
+                } else if let Ok(f) = value.parse() {
+                    toml::Value::Float(f)
+                } else {
+                    toml::Value::String(value)
+                }
+            };
+            println!("{} = {} # {}", id_to_key(id), value, source);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{split_words, WordsFrequencyMap};
+use anyhow::{bail, Context, Result};
+use serde_derive::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct CooccurConfig {
+    targets: Arc<HashSet<String>>,
+    window: usize,
+    limit: usize,
+}
+
+impl CooccurConfig {
+    // One target word per line. Targets are matched lowercased, like the lowercase frequency list.
+    pub fn load(path: &str, window: usize, limit: usize) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read target words from {}", path))?;
+        let targets: HashSet<String> = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_ascii_lowercase)
+            .collect();
+        if targets.is_empty() {
+            bail!("No target words found in {}", path);
+        }
+        Ok(Self {
+            targets: Arc::new(targets),
+            window,
+            limit,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct Neighbor {
+    pub word: String,
+    pub count: u32,
+    pub pmi: f64,
+}
+
+#[derive(Serialize)]
+pub struct TargetCooccurrences {
+    pub target: String,
+    pub appearances: u32,
+    pub neighbors: Vec<Neighbor>,
+}
+
+pub struct CooccurStats {
+    config: CooccurConfig,
+    neighbors: HashMap<String, HashMap<String, u32>>,
+}
+
+impl CooccurStats {
+    pub fn new(config: &CooccurConfig) -> Self {
+        Self {
+            config: config.clone(),
+            neighbors: HashMap::new(),
+        }
+    }
+
+    // Windowed pass over the same tokens as WordsFrequencyMap::map_words: every pair of words
+    // at most `window` tokens apart is counted once for each of them that is a target.
+    pub fn map_words(&mut self, s: &str) {
+        let mut previous: VecDeque<String> = VecDeque::with_capacity(self.config.window);
+        for word in split_words(s) {
+            let word = word.to_ascii_lowercase();
+            let is_target = self.config.targets.contains(&word);
+            for other in &previous {
+                if is_target {
+                    Self::add_to(&mut self.neighbors, &word, other);
+                }
+                if self.config.targets.contains(other) {
+                    Self::add_to(&mut self.neighbors, other, &word);
+                }
+            }
+            if previous.len() == self.config.window {
+                previous.pop_front();
+            }
+            previous.push_back(word);
+        }
+    }
+
+    fn add_to(neighbors: &mut HashMap<String, HashMap<String, u32>>, target: &str, word: &str) {
+        let counts = match neighbors.get_mut(target) {
+            Some(counts) => counts,
+            None => neighbors.entry(target.to_string()).or_default(),
+        };
+        match counts.get_mut(word) {
+            Some(count) => *count += 1,
+            None => {
+                counts.insert(word.to_string(), 1);
+            }
+        }
+    }
+
+    pub fn merge_with(&mut self, other: Self) {
+        for (target, other_counts) in other.neighbors {
+            let counts = self.neighbors.entry(target).or_default();
+            for (word, count) in other_counts {
+                *counts.entry(word).or_insert(0) += count;
+            }
+        }
+    }
+
+    // PMI = log2(p(target, word) / (p(target) * p(word))), where a target occurrence offers
+    // 2 * window neighbor positions: log2(count * total / (c(target) * c(word) * 2 * window)).
+    // Needs the merged lowercase frequency list for c(target), c(word) & the total.
+    pub fn finish(self, low_words: &WordsFrequencyMap) -> Vec<TargetCooccurrences> {
+        let total: u64 = low_words.pairs.values().map(|&count| count as u64).sum();
+        let window_positions = 2.0 * self.config.window as f64;
+        let mut neighbors = self.neighbors;
+        let mut targets: Vec<&String> = self.config.targets.iter().collect();
+        targets.sort_unstable();
+        targets
+            .into_iter()
+            .map(|target| {
+                let appearances = low_words.pairs.get(target).copied().unwrap_or(0);
+                let mut target_neighbors: Vec<Neighbor> = neighbors
+                    .remove(target)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(word, count)| {
+                        let word_count = low_words.pairs.get(&word).copied().unwrap_or(count);
+                        let pmi = (count as f64 * total as f64
+                            / (appearances.max(1) as f64 * word_count as f64 * window_positions))
+                            .log2();
+                        Neighbor { word, count, pmi }
+                    })
+                    .collect();
+                target_neighbors.sort_unstable_by(|a, b| {
+                    b.count.cmp(&a.count).then_with(|| a.word.cmp(&b.word))
+                });
+                target_neighbors.truncate(self.config.limit);
+                TargetCooccurrences {
+                    target: target.clone(),
+                    appearances,
+                    neighbors: target_neighbors,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairs_within_the_window() {
+        let config = CooccurConfig {
+            targets: Arc::new(["river".to_string(), "sea".to_string()].into()),
+            window: 2,
+            limit: 3,
+        };
+        let text = "a b River c d e river f";
+        let mut cooccur = CooccurStats::new(&config);
+        cooccur.map_words(text);
+        let mut low_words = WordsFrequencyMap::new();
+        WordsFrequencyMap::map_words(None, Some(&mut low_words), text);
+
+        // The first "river" has a, b, c & d as neighbors, the second d, e & f; the c 3 words
+        // before the second one is out of its window.
+        let expected: HashMap<String, u32> =
+            [("a", 1), ("b", 1), ("c", 1), ("d", 2), ("e", 1), ("f", 1)]
+                .into_iter()
+                .map(|(word, count)| (word.to_string(), count))
+                .collect();
+        assert_eq!(cooccur.neighbors["river"], expected);
+
+        let targets = cooccur.finish(&low_words);
+        assert_eq!(targets.len(), 2);
+        let river = &targets[0];
+        assert_eq!((river.target.as_str(), river.appearances), ("river", 2));
+        // log2(count * 8 words / (2 rivers * 1 * 2 * 2 positions)): 1 for d, 0 for the others.
+        let neighbors: Vec<(&str, u32, f64)> = river
+            .neighbors
+            .iter()
+            .map(|neighbor| (neighbor.word.as_str(), neighbor.count, neighbor.pmi))
+            .collect();
+        assert_eq!(neighbors, [("d", 2, 1.0), ("a", 1, 0.0), ("b", 1, 0.0)]);
+        let sea = &targets[1];
+        assert_eq!((sea.target.as_str(), sea.appearances), ("sea", 0));
+        assert!(sea.neighbors.is_empty());
+    }
+}
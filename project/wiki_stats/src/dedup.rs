@@ -0,0 +1,356 @@
+use crate::{split_words, Article};
+use serde_derive::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+
+const SIGNATURE_SIZE: usize = 128;
+// Locality-sensitive hashing: articles are only compared when all the values of one band of
+// their signatures match. 32 bands of 4 rows find pairs above ~0.6 similarity almost surely.
+const BANDS: usize = 32;
+const ROWS: usize = SIGNATURE_SIZE / BANDS;
+// Number of consecutive words of a shingle.
+const SHINGLE_SIZE: usize = 3;
+
+pub fn parse_threshold(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(threshold) if (0.0..=1.0).contains(&threshold) => Ok(threshold),
+        _ => Err(format!(
+            "invalid threshold '{}' (must be between 0 and 1)",
+            s
+        )),
+    }
+}
+
+fn hash_of(value: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+// splitmix64, used to derive the signature's hash functions from a single shingle hash.
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E3779B97F4A7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+    x ^ (x >> 31)
+}
+
+fn min_hash_signature(words: &[String]) -> Vec<u32> {
+    let mut signature = vec![u32::MAX; SIGNATURE_SIZE];
+    for shingle in words.windows(SHINGLE_SIZE.min(words.len())) {
+        let shingle_hash = hash_of(shingle);
+        for (i, min) in signature.iter_mut().enumerate() {
+            *min = (*min).min(mix(shingle_hash ^ ((i as u64) << 32)) as u32);
+        }
+    }
+    signature
+}
+
+fn similarity(a: &[u32], b: &[u32]) -> f64 {
+    a.iter().zip(b).filter(|(x, y)| x == y).count() as f64 / SIGNATURE_SIZE as f64
+}
+
+struct KeptArticle {
+    path: String,
+    signature: Vec<u32>,
+}
+
+// A hash of the text isn't enough to call two articles exact duplicates: a collision would drop
+// a distinct article. Its length & a second, independent hash have to match too.
+type ContentKey = (u64, usize, u64);
+
+fn content_key(text: &str) -> ContentKey {
+    const SECOND_HASH_SEED: u64 = 0x5EED;
+    (hash_of(text), text.len(), hash_of((SECOND_HASH_SEED, text)))
+}
+
+struct Fingerprint {
+    path: String,
+    content: ContentKey,
+    signature: Vec<u32>,
+    band_keys: Vec<u64>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct Duplicate {
+    pub path: String,
+    pub exact: bool,
+    pub similarity: f64,
+}
+
+#[derive(Serialize)]
+pub struct DuplicateCluster {
+    pub article: String,
+    pub duplicates: Vec<Duplicate>,
+}
+
+#[derive(Serialize)]
+pub struct DedupReport {
+    pub articles_checked: u64,
+    pub exact_duplicates: u64,
+    pub near_duplicates: u64,
+    pub threshold: f64,
+    pub excluded_from_counts: bool,
+    #[serde(skip)]
+    pub clusters: Vec<DuplicateCluster>,
+}
+
+#[derive(Default)]
+struct DedupIndex {
+    kept: Vec<KeptArticle>,
+    by_content: HashMap<ContentKey, usize>,
+    by_band: HashMap<u64, Vec<usize>>,
+    duplicates: HashMap<usize, Vec<Duplicate>>,
+    articles_checked: u64,
+}
+
+// Passes the turn of its file when dropped, unless the file was checked: the workers of the
+// next files would otherwise wait forever.
+pub struct TurnGuard {
+    turn: Arc<(Mutex<usize>, Condvar)>,
+    file_index: usize,
+}
+
+impl Drop for TurnGuard {
+    fn drop(&mut self) {
+        let (next_file, turn_changed) = &*self.turn;
+        let mut next_file = next_file.lock().unwrap_or_else(PoisonError::into_inner);
+        if *next_file > self.file_index {
+            return;
+        }
+        while *next_file != self.file_index {
+            next_file = turn_changed
+                .wait(next_file)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        *next_file += 1;
+        turn_changed.notify_all();
+    }
+}
+
+// Shared by all workers: the first article seen with some content is kept, the later ones
+// with the same (or similar enough) content are its duplicates.
+#[derive(Clone)]
+pub struct DedupConfig {
+    index: Arc<Mutex<DedupIndex>>,
+    // Index of the next file to register & the condition its worker waits on.
+    turn: Arc<(Mutex<usize>, Condvar)>,
+    threshold: f64,
+    pub exclude: bool,
+}
+
+impl DedupConfig {
+    pub fn new(threshold: f64, exclude: bool) -> Self {
+        Self {
+            index: Arc::new(Mutex::new(DedupIndex::default())),
+            turn: Arc::new((Mutex::new(0), Condvar::new())),
+            threshold,
+            exclude,
+        }
+    }
+
+    // Taken by the worker of each file before anything can fail, so that its turn is passed
+    // even if the file is never checked (unreadable, malformed...).
+    pub fn turn_guard(&self, file_index: usize) -> TurnGuard {
+        TurnGuard {
+            turn: Arc::clone(&self.turn),
+            file_index,
+        }
+    }
+
+    // Articles without words are skipped.
+    fn fingerprint(article: &Article, path: &str) -> Option<Fingerprint> {
+        let words: Vec<String> = split_words(&article.text).map(str::to_lowercase).collect();
+        if words.is_empty() {
+            return None;
+        }
+        let signature = min_hash_signature(&words);
+        Some(Fingerprint {
+            path: format!("{}/{}", path, article.id),
+            content: content_key(article.text.trim()),
+            band_keys: signature
+                .chunks(ROWS)
+                .enumerate()
+                .map(|(band, rows)| hash_of((band, rows)))
+                .collect(),
+            signature,
+        })
+    }
+
+    // Returns whether each article of the file duplicates one seen before. The signatures are
+    // computed concurrently by the workers, but the files (numbered from 0 in the order of the
+    // archive) are registered one at a time, in order, so that which article of a cluster is
+    // kept doesn't depend on thread timing.
+    pub fn check_file(&self, file_index: usize, articles: &[Article], path: &str) -> Vec<bool> {
+        let fingerprints: Vec<Option<Fingerprint>> = articles
+            .iter()
+            .map(|article| Self::fingerprint(article, path))
+            .collect();
+        let (next_file, turn_changed) = &*self.turn;
+        let mut next_file = next_file.lock().unwrap_or_else(PoisonError::into_inner);
+        while *next_file != file_index {
+            next_file = turn_changed
+                .wait(next_file)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        let duplicates = fingerprints
+            .into_iter()
+            .map(|fingerprint| fingerprint.is_some_and(|f| self.register(f)))
+            .collect();
+        *next_file += 1;
+        turn_changed.notify_all();
+        duplicates
+    }
+
+    fn register(&self, fingerprint: Fingerprint) -> bool {
+        let Fingerprint {
+            path,
+            content,
+            signature,
+            band_keys,
+        } = fingerprint;
+        let mut index = self.index.lock().unwrap_or_else(PoisonError::into_inner);
+        index.articles_checked += 1;
+        if let Some(&kept) = index.by_content.get(&content) {
+            index.duplicates.entry(kept).or_default().push(Duplicate {
+                path,
+                exact: true,
+                similarity: 1.0,
+            });
+            return true;
+        }
+        let mut best: Option<(usize, f64)> = None;
+        for key in &band_keys {
+            for &candidate in index.by_band.get(key).into_iter().flatten() {
+                let candidate_similarity = similarity(&signature, &index.kept[candidate].signature);
+                let is_best = match best {
+                    Some((_, best_similarity)) => candidate_similarity > best_similarity,
+                    None => true,
+                };
+                if candidate_similarity >= self.threshold && is_best {
+                    best = Some((candidate, candidate_similarity));
+                }
+            }
+        }
+        if let Some((kept, best_similarity)) = best {
+            index.duplicates.entry(kept).or_default().push(Duplicate {
+                path,
+                exact: false,
+                similarity: best_similarity,
+            });
+            return true;
+        }
+
+        let kept = index.kept.len();
+        index.kept.push(KeptArticle { path, signature });
+        index.by_content.insert(content, kept);
+        for key in band_keys {
+            index.by_band.entry(key).or_default().push(kept);
+        }
+        false
+    }
+
+    // Clusters sorted by size (largest first), then by path of the kept article.
+    pub fn report(&self) -> DedupReport {
+        let index = self.index.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut clusters: Vec<DuplicateCluster> = index
+            .duplicates
+            .iter()
+            .map(|(&kept, duplicates)| {
+                let mut duplicates = duplicates.to_vec();
+                duplicates.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+                DuplicateCluster {
+                    article: index.kept[kept].path.clone(),
+                    duplicates,
+                }
+            })
+            .collect();
+        clusters.sort_unstable_by(|a, b| {
+            b.duplicates
+                .len()
+                .cmp(&a.duplicates.len())
+                .then_with(|| a.article.cmp(&b.article))
+        });
+        let all_duplicates = clusters.iter().flat_map(|cluster| &cluster.duplicates);
+        DedupReport {
+            articles_checked: index.articles_checked,
+            exact_duplicates: all_duplicates.clone().filter(|d| d.exact).count() as u64,
+            near_duplicates: all_duplicates.filter(|d| !d.exact).count() as u64,
+            threshold: self.threshold,
+            excluded_from_counts: self.exclude,
+            clusters,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn article(id: &str, text: &str) -> Article {
+        Article {
+            id: id.to_string(),
+            title: String::new(),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn exact_and_near_duplicates() {
+        let dedup = DedupConfig::new(0.5, false);
+        let text = "one two three four five six seven eight nine ten";
+        let articles = [
+            article("1", text),
+            article("2", &format!("  {}\n", text)),
+            article("3", &format!("{} eleven", text)),
+            article("4", "something else entirely, with other words"),
+            article("5", ""),
+        ];
+        assert_eq!(
+            dedup.check_file(0, &articles, "a.json"),
+            [false, true, true, false, false]
+        );
+        let report = dedup.report();
+        assert_eq!(
+            (
+                report.articles_checked,
+                report.exact_duplicates,
+                report.near_duplicates
+            ),
+            (4, 1, 1)
+        );
+    }
+
+    #[test]
+    fn hash_collisions_are_not_exact_duplicates() {
+        let dedup = DedupConfig::new(1.0, false);
+        let first = DedupConfig::fingerprint(&article("1", "some text"), "a.json").unwrap();
+        let mut second = DedupConfig::fingerprint(&article("2", "other words"), "a.json").unwrap();
+        // Same first hash, as if the texts collided.
+        second.content.0 = first.content.0;
+        assert!(!dedup.register(first));
+        assert!(!dedup.register(second));
+    }
+
+    #[test]
+    fn failed_files_pass_their_turn() {
+        let dedup = DedupConfig::new(0.8, false);
+        let (sender, receiver) = mpsc::channel();
+        let worker = dedup.clone();
+        thread::spawn(move || {
+            let _turn = worker.turn_guard(1);
+            worker.check_file(1, &[article("1", "some words")], "b.json");
+            sender.send(()).unwrap();
+        });
+        // The worker of file 0 fails before checking it.
+        drop(dedup.turn_guard(0));
+        receiver
+            .recv_timeout(Duration::from_secs(10))
+            .expect("file 1 waited for the turn of file 0");
+    }
+}
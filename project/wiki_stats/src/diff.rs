@@ -0,0 +1,297 @@
+use crate::{LongestItem, StatsPackage, WordsFrequencyMap};
+use std::cmp::Ordering;
+
+#[derive(Clone)]
+struct WordChange {
+    word: String,
+    old_count: u32,
+    new_count: u32,
+}
+
+impl WordChange {
+    fn absolute(&self) -> i64 {
+        self.new_count as i64 - self.old_count as i64
+    }
+
+    fn relative(&self) -> f64 {
+        self.absolute() as f64 / self.old_count as f64
+    }
+}
+
+pub struct StatsDiff {
+    old_distinct: usize,
+    new_distinct: usize,
+    old_total: u64,
+    new_total: u64,
+    new_words: Vec<(String, u32)>,
+    vanished_words: Vec<(String, u32)>,
+    absolute_changes: Vec<WordChange>,
+    relative_changes: Vec<WordChange>,
+    new_words_count: usize,
+    vanished_words_count: usize,
+}
+
+fn by_count_then_word(a: &(String, u32), b: &(String, u32)) -> Ordering {
+    b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0))
+}
+
+impl StatsDiff {
+    // Only `limit` entries of each list are kept; relative changes ignore words seen
+    // fewer than `min_count` times in both results, as those are mostly noise.
+    pub fn new(
+        old: &WordsFrequencyMap,
+        new: &WordsFrequencyMap,
+        limit: usize,
+        min_count: u32,
+    ) -> Self {
+        let mut new_words: Vec<(String, u32)> = vec![];
+        let mut vanished_words: Vec<(String, u32)> = vec![];
+        let mut changes: Vec<WordChange> = vec![];
+
+        for (word, &new_count) in &new.pairs {
+            match old.pairs.get(word) {
+                Some(&old_count) if old_count != new_count => changes.push(WordChange {
+                    word: word.clone(),
+                    old_count,
+                    new_count,
+                }),
+                Some(_) => {}
+                None => new_words.push((word.clone(), new_count)),
+            }
+        }
+        for (word, &old_count) in &old.pairs {
+            if !new.pairs.contains_key(word) {
+                vanished_words.push((word.clone(), old_count));
+            }
+        }
+
+        let new_words_count = new_words.len();
+        let vanished_words_count = vanished_words.len();
+        new_words.sort_unstable_by(by_count_then_word);
+        new_words.truncate(limit);
+        vanished_words.sort_unstable_by(by_count_then_word);
+        vanished_words.truncate(limit);
+
+        changes.sort_unstable_by(|a, b| {
+            b.absolute()
+                .abs()
+                .cmp(&a.absolute().abs())
+                .then_with(|| a.word.cmp(&b.word))
+        });
+        let mut relative_changes: Vec<WordChange> = changes
+            .iter()
+            .filter(|change| change.old_count.max(change.new_count) >= min_count)
+            .cloned()
+            .collect();
+        relative_changes.sort_unstable_by(|a, b| {
+            b.relative()
+                .abs()
+                .total_cmp(&a.relative().abs())
+                .then_with(|| a.word.cmp(&b.word))
+        });
+        relative_changes.truncate(limit);
+        changes.truncate(limit);
+
+        Self {
+            old_distinct: old.pairs.len(),
+            new_distinct: new.pairs.len(),
+            old_total: old.pairs.values().map(|&count| count as u64).sum(),
+            new_total: new.pairs.values().map(|&count| count as u64).sum(),
+            new_words,
+            vanished_words,
+            absolute_changes: changes,
+            relative_changes,
+            new_words_count,
+            vanished_words_count,
+        }
+    }
+
+    pub fn print(&self) {
+        println!(
+            "Distinct words: {} -> {} ({:+})",
+            self.old_distinct,
+            self.new_distinct,
+            self.new_distinct as i64 - self.old_distinct as i64
+        );
+        println!(
+            "Total words: {} -> {} ({:+})",
+            self.old_total,
+            self.new_total,
+            self.new_total as i64 - self.old_total as i64
+        );
+
+        println!("\n\tNew words ({} in total)\n", self.new_words_count);
+        for (word, count) in &self.new_words {
+            println!("{}: {}", word, count);
+        }
+
+        println!(
+            "\n\tVanished words ({} in total)\n",
+            self.vanished_words_count
+        );
+        for (word, count) in &self.vanished_words {
+            println!("{}: {}", word, count);
+        }
+
+        println!("\n\tLargest absolute changes\n");
+        for change in &self.absolute_changes {
+            println!(
+                "{}: {} -> {} ({:+})",
+                change.word,
+                change.old_count,
+                change.new_count,
+                change.absolute()
+            );
+        }
+
+        println!("\n\tLargest relative changes\n");
+        for change in &self.relative_changes {
+            println!(
+                "{}: {} -> {} ({:+.2}%)",
+                change.word,
+                change.old_count,
+                change.new_count,
+                change.relative() * 100.0
+            );
+        }
+    }
+}
+
+fn print_longest_change(label: &str, old: &LongestItem, new: &LongestItem) {
+    if old.title == new.title && old.path == new.path && old.size == new.size {
+        println!("\n\t{} (unchanged)\n", label);
+        println!(
+            "Title: {}\nPath: {}\nSize: {}",
+            new.title, new.path, new.size
+        );
+    } else {
+        println!("\n\t{} (changed)\n", label);
+        println!(
+            "Title: {} -> {}\nPath: {} -> {}\nSize: {} -> {} ({:+})",
+            old.title,
+            new.title,
+            old.path,
+            new.path,
+            old.size,
+            new.size,
+            new.size as i64 - old.size as i64
+        );
+    }
+}
+
+pub fn print_stats_diff(
+    old: &StatsPackage,
+    new: &StatsPackage,
+    lowercase: bool,
+    limit: usize,
+    min_count: u32,
+) {
+    let diff = if lowercase {
+        StatsDiff::new(&old.low_words_freq, &new.low_words_freq, limit, min_count)
+    } else {
+        StatsDiff::new(&old.words_freq, &new.words_freq, limit, min_count)
+    };
+    diff.print();
+    print_longest_change("Longest article", &old.long_art, &new.long_art);
+    print_longest_change("Longest title", &old.long_title, &new.long_title);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(pairs: &[(&str, u32)]) -> StatsPackage {
+        let mut stats = StatsPackage::new();
+        for &(word, count) in pairs {
+            stats.words_freq.pairs.insert(word.to_string(), count);
+        }
+        stats
+    }
+
+    fn changes(changes: &[WordChange]) -> Vec<(&str, u32, u32)> {
+        changes
+            .iter()
+            .map(|change| (change.word.as_str(), change.old_count, change.new_count))
+            .collect()
+    }
+
+    fn example_diff(limit: usize, min_count: u32) -> StatsDiff {
+        let old = stats(&[
+            ("the", 100),
+            ("river", 10),
+            ("boat", 4),
+            ("ship", 2),
+            ("same", 5),
+            ("gone", 5),
+            ("lost", 1),
+        ]);
+        let new = stats(&[
+            ("the", 150),
+            ("river", 30),
+            ("boat", 12),
+            ("ship", 3),
+            ("same", 5),
+            ("fresh", 7),
+            ("new", 2),
+            ("late", 2),
+        ]);
+        StatsDiff::new(&old.words_freq, &new.words_freq, limit, min_count)
+    }
+
+    #[test]
+    fn new_and_vanished_words() {
+        let diff = example_diff(2, 10);
+        assert_eq!((diff.old_distinct, diff.new_distinct), (7, 8));
+        assert_eq!((diff.old_total, diff.new_total), (127, 211));
+        // Sorted by count, then alphabetically, & cut to the limit.
+        assert_eq!(diff.new_words_count, 3);
+        assert_eq!(
+            diff.new_words,
+            [("fresh".to_string(), 7), ("late".to_string(), 2)]
+        );
+        assert_eq!(diff.vanished_words_count, 2);
+        assert_eq!(
+            diff.vanished_words,
+            [("gone".to_string(), 5), ("lost".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn absolute_and_relative_changes() {
+        let diff = example_diff(10, 1);
+        // Unchanged words aren't changes.
+        assert_eq!(
+            changes(&diff.absolute_changes),
+            [
+                ("the", 100, 150),
+                ("river", 10, 30),
+                ("boat", 4, 12),
+                ("ship", 2, 3)
+            ]
+        );
+        // +200% for boat & river, +50% for ship & the.
+        assert_eq!(
+            changes(&diff.relative_changes),
+            [
+                ("boat", 4, 12),
+                ("river", 10, 30),
+                ("ship", 2, 3),
+                ("the", 100, 150)
+            ]
+        );
+    }
+
+    #[test]
+    fn rare_words_are_left_out_of_relative_changes() {
+        // ship (2 -> 3) is seen fewer than 10 times in both, boat only in the new one.
+        let diff = example_diff(10, 10);
+        assert_eq!(
+            changes(&diff.relative_changes),
+            [("boat", 4, 12), ("river", 10, 30), ("the", 100, 150)]
+        );
+        assert_eq!(diff.absolute_changes.len(), 4);
+
+        let diff = example_diff(10, 31);
+        assert_eq!(changes(&diff.relative_changes), [("the", 100, 150)]);
+    }
+}
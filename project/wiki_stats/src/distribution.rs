@@ -0,0 +1,80 @@
+use anyhow::Result;
+use serde_derive::Serialize;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+// Ranks used for the Zipf fit grow geometrically by this factor, so that every order of
+// magnitude weighs the same and the long tail of rare words doesn't swamp the fit.
+const RANK_STEP: f64 = 1.1;
+
+#[derive(Serialize)]
+pub struct WordLength {
+    pub length: usize,
+    pub occurrences: u64,
+    pub distinct_words: u64,
+}
+
+#[derive(Serialize)]
+pub struct Distribution {
+    pub zipf_exponent: Option<f64>,
+    pub zipf_r_squared: Option<f64>,
+    pub zipf_ranks_fitted: usize,
+    pub word_lengths: Vec<WordLength>,
+}
+
+// Least squares fit of log(count) = log(c) - s * log(rank); returns s & the coefficient
+// of determination (R²) of the fit.
+fn fit_zipf(counts: &[u32]) -> (Option<f64>, Option<f64>, usize) {
+    let mut points: Vec<(f64, f64)> = vec![];
+    let mut rank = 1;
+    while rank <= counts.len() {
+        points.push(((rank as f64).ln(), (counts[rank - 1] as f64).ln()));
+        rank = ((rank as f64 * RANK_STEP).ceil() as usize).max(rank + 1);
+    }
+    let n = points.len() as f64;
+    if points.len() < 2 {
+        return (None, None, points.len());
+    }
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    let sxy: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    let syy: f64 = points.iter().map(|p| (p.1 - mean_y).powi(2)).sum();
+    let slope = sxy / sxx;
+    let r_squared = if syy > 0.0 {
+        Some(1.0 - (syy - slope * sxy) / syy)
+    } else {
+        None
+    };
+    (Some(-slope), r_squared, points.len())
+}
+
+impl Distribution {
+    // Takes the length (in chars) & the count of every word.
+    pub fn from_words(words: impl Iterator<Item = Result<(usize, u32)>>) -> Result<Self> {
+        let mut lengths: BTreeMap<usize, (u64, u64)> = BTreeMap::new();
+        let mut counts: Vec<u32> = vec![];
+        for pair in words {
+            let (length, count) = pair?;
+            let length = lengths.entry(length).or_insert((0, 0));
+            length.0 += count as u64;
+            length.1 += 1;
+            counts.push(count);
+        }
+        counts.sort_unstable_by_key(|&count| Reverse(count));
+        let (zipf_exponent, zipf_r_squared, zipf_ranks_fitted) = fit_zipf(&counts);
+        Ok(Self {
+            zipf_exponent,
+            zipf_r_squared,
+            zipf_ranks_fitted,
+            word_lengths: lengths
+                .into_iter()
+                .map(|(length, (occurrences, distinct_words))| WordLength {
+                    length,
+                    occurrences,
+                    distinct_words,
+                })
+                .collect(),
+        })
+    }
+}
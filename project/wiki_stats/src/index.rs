@@ -0,0 +1,459 @@
+use crate::compress;
+use crate::output::{OutputFile, OutputTarget};
+use crate::spill::SortedWords;
+use crate::{LongestItem, StatsPackage, WordsFrequencyMap};
+use anyhow::{bail, Context, Result};
+use memmap2::Mmap;
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+const INDEX_MAGIC: &[u8; 8] = b"WSTATIDX";
+const INDEX_VERSION: u32 = 1;
+
+// Layout of an index file (all integers little-endian):
+//   magic, version, longest article, longest title, as-written table, lowercase table.
+// A table is: n (u64), n + 1 word offsets (u64), n counts (u32),
+// n word indices ordered by count (u32), n ranks (u32), then the concatenated words.
+// Words are sorted, so lookups and prefix scans are binary searches over the offsets.
+
+fn write_longest_item(w: &mut impl Write, item: &LongestItem) -> Result<()> {
+    w.write_all(&(item.title.len() as u64).to_le_bytes())?;
+    w.write_all(item.title.as_bytes())?;
+    w.write_all(&(item.path.len() as u64).to_le_bytes())?;
+    w.write_all(item.path.as_bytes())?;
+    w.write_all(&(item.size as u64).to_le_bytes())?;
+    Ok(())
+}
+
+// Reads the sorted words three times (counts, offsets, words), so that only the counts
+// and ranks have to be kept in memory when the words were spilled to disk.
+fn write_table(w: &mut impl Write, words: SortedWords) -> Result<()> {
+    let mut counts: Vec<u32> = vec![];
+    for pair in words.iter()? {
+        counts.push(pair?.1);
+    }
+
+    let mut by_count: Vec<u32> = (0..counts.len() as u32).collect();
+    by_count.sort_unstable_by(|&a, &b| counts[b as usize].cmp(&counts[a as usize]).then(a.cmp(&b)));
+    let mut ranks = vec![0u32; counts.len()];
+    for (rank, &index) in by_count.iter().enumerate() {
+        ranks[index as usize] = rank as u32;
+    }
+
+    w.write_all(&(counts.len() as u64).to_le_bytes())?;
+    let mut offset: u64 = 0;
+    w.write_all(&offset.to_le_bytes())?;
+    for pair in words.iter()? {
+        offset += pair?.0.len() as u64;
+        w.write_all(&offset.to_le_bytes())?;
+    }
+    for count in &counts {
+        w.write_all(&count.to_le_bytes())?;
+    }
+    for index in &by_count {
+        w.write_all(&index.to_le_bytes())?;
+    }
+    for rank in &ranks {
+        w.write_all(&rank.to_le_bytes())?;
+    }
+    for pair in words.iter()? {
+        w.write_all(pair?.0.as_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn write_stats_to_file_index(stats: &mut StatsPackage, target: &OutputTarget) -> Result<()> {
+    let mut writer = BufWriter::new(OutputFile::create(target)?);
+
+    writer.write_all(INDEX_MAGIC)?;
+    writer.write_all(&INDEX_VERSION.to_le_bytes())?;
+    write_longest_item(&mut writer, &stats.long_art)?;
+    write_longest_item(&mut writer, &stats.long_title)?;
+    write_table(&mut writer, stats.sorted_words(false)?)?;
+    write_table(&mut writer, stats.sorted_words(true)?)?;
+    writer.into_inner().map_err(|e| e.into_error())?.finish()
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .context("Index file is truncated")?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn read_len(&mut self) -> Result<usize> {
+        usize::try_from(self.read_u64()?).context("Index file is corrupted")
+    }
+
+    fn read_string(&mut self) -> Result<String> {
+        let len = self.read_len()?;
+        Ok(String::from_utf8(self.take(len)?.to_vec())?)
+    }
+
+    fn read_longest_item(&mut self) -> Result<LongestItem> {
+        Ok(LongestItem {
+            title: self.read_string()?,
+            path: self.read_string()?,
+            size: self.read_len()?,
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+struct TableBounds {
+    len: usize,
+    offsets: usize,
+    counts: usize,
+    by_count: usize,
+    ranks: usize,
+    words: usize,
+    words_len: usize,
+}
+
+impl TableBounds {
+    // Only checks that the table fits in the file: its entries are validated when they're
+    // looked up, so that opening an index doesn't read all of it.
+    fn read(cursor: &mut Cursor) -> Result<Self> {
+        let len = cursor.read_len()?;
+        let table_len = |width: usize| len.checked_mul(width).context("Index file is corrupted");
+
+        let offsets = cursor.pos;
+        let offsets_bytes = cursor.take(
+            table_len(8)?
+                .checked_add(8)
+                .context("Index file is corrupted")?,
+        )?;
+        let counts = cursor.pos;
+        cursor.take(table_len(4)?)?;
+        let by_count = cursor.pos;
+        cursor.take(table_len(4)?)?;
+        let ranks = cursor.pos;
+        cursor.take(table_len(4)?)?;
+
+        let words_len = usize::try_from(u64::from_le_bytes(
+            offsets_bytes[len * 8..len * 8 + 8].try_into()?,
+        ))
+        .context("Index file is corrupted")?;
+        let words = cursor.pos;
+        cursor.take(words_len)?;
+
+        Ok(Self {
+            len,
+            offsets,
+            counts,
+            by_count,
+            ranks,
+            words,
+            words_len,
+        })
+    }
+}
+
+// Uncompressed index files are mapped rather than read, so that queries only load the pages
+// they look at; compressed ones have to be decompressed in memory.
+enum IndexData {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl IndexData {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            IndexData::Mapped(map) => map,
+            IndexData::Owned(data) => data,
+        }
+    }
+}
+
+pub struct StatsIndex {
+    data: IndexData,
+    long_art: LongestItem,
+    long_title: LongestItem,
+    words_freq: TableBounds,
+    low_words_freq: TableBounds,
+}
+
+impl StatsIndex {
+    pub fn open(index_file_path: &str) -> Result<Self> {
+        let read_error = || format!("Failed to read index file {}", index_file_path);
+        let data = if compress::is_compressed(index_file_path).with_context(read_error)? {
+            IndexData::Owned(compress::read_file(index_file_path)?)
+        } else {
+            let file = File::open(index_file_path).with_context(read_error)?;
+            // Safety: the index mustn't be modified while it's queried (the process would get a
+            // SIGBUS if it were truncated), as for the archives.
+            IndexData::Mapped(unsafe { Mmap::map(&file) }.with_context(read_error)?)
+        };
+        Self::load(data).with_context(|| format!("Failed to open index file {}", index_file_path))
+    }
+
+    // For a file already read (& decompressed) whole, e.g. by the reader of all formats.
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        Self::load(IndexData::Owned(data))
+    }
+
+    fn load(data: IndexData) -> Result<Self> {
+        let mut cursor = Cursor {
+            data: data.bytes(),
+            pos: 0,
+        };
+        if cursor.take(INDEX_MAGIC.len()).ok() != Some(INDEX_MAGIC.as_slice()) {
+            bail!("Not a wiki_stats index file");
+        }
+        let version = cursor.read_u32()?;
+        if version != INDEX_VERSION {
+            bail!("Unsupported index file version: {}", version);
+        }
+        let long_art = cursor.read_longest_item()?;
+        let long_title = cursor.read_longest_item()?;
+        let words_freq = TableBounds::read(&mut cursor)?;
+        let low_words_freq = TableBounds::read(&mut cursor)?;
+
+        Ok(Self {
+            data,
+            long_art,
+            long_title,
+            words_freq,
+            low_words_freq,
+        })
+    }
+
+    pub fn longest_article(&self) -> &LongestItem {
+        &self.long_art
+    }
+
+    pub fn longest_title(&self) -> &LongestItem {
+        &self.long_title
+    }
+
+    pub fn into_stats(self) -> Result<StatsPackage> {
+        let to_map = |table: IndexTable| -> Result<WordsFrequencyMap> {
+            Ok(WordsFrequencyMap {
+                pairs: (0..table.len())
+                    .map(|i| Ok((table.word(i)?.to_string(), table.count(i))))
+                    .collect::<Result<_>>()?,
+            })
+        };
+        let words_freq = to_map(self.table(false))?;
+        let low_words_freq = to_map(self.table(true))?;
+        Ok(StatsPackage {
+            words_freq,
+            low_words_freq,
+            long_art: self.long_art,
+            long_title: self.long_title,
+            ..StatsPackage::new()
+        })
+    }
+
+    pub fn table(&self, lowercase: bool) -> IndexTable<'_> {
+        let bounds = if lowercase {
+            self.low_words_freq
+        } else {
+            self.words_freq
+        };
+        IndexTable {
+            len: bounds.len,
+            offsets: &self.data.bytes()[bounds.offsets..bounds.counts],
+            counts: &self.data.bytes()[bounds.counts..bounds.by_count],
+            by_count: &self.data.bytes()[bounds.by_count..bounds.ranks],
+            ranks: &self.data.bytes()[bounds.ranks..bounds.words],
+            words: &self.data.bytes()[bounds.words..bounds.words + bounds.words_len],
+        }
+    }
+}
+
+/// A view over one frequency table of an index. Entries are addressed by their
+/// position in alphabetical order. Words & positions are validated as they're read, a corrupted
+/// index giving an error.
+pub struct IndexTable<'a> {
+    len: usize,
+    offsets: &'a [u8],
+    counts: &'a [u8],
+    by_count: &'a [u8],
+    ranks: &'a [u8],
+    words: &'a [u8],
+}
+
+impl<'a> IndexTable<'a> {
+    fn u32_at(bytes: &[u8], i: usize) -> u32 {
+        u32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap())
+    }
+
+    fn offset_at(&self, i: usize) -> usize {
+        u64::from_le_bytes(self.offsets[i * 8..i * 8 + 8].try_into().unwrap()) as usize
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn word(&self, i: usize) -> Result<&'a str> {
+        self.words
+            .get(self.offset_at(i)..self.offset_at(i + 1))
+            .and_then(|word| std::str::from_utf8(word).ok())
+            .context("Index file is corrupted")
+    }
+
+    pub fn count(&self, i: usize) -> u32 {
+        Self::u32_at(self.counts, i)
+    }
+
+    // Ranks & the entries ordered by count are positions in the table.
+    fn position(&self, position: u32) -> Result<usize> {
+        match position as usize {
+            position if position < self.len => Ok(position),
+            _ => bail!("Index file is corrupted"),
+        }
+    }
+
+    /// 0-based position of the entry when ordered by count (descending).
+    pub fn rank(&self, i: usize) -> Result<usize> {
+        self.position(Self::u32_at(self.ranks, i))
+    }
+
+    pub fn at_rank(&self, rank: usize) -> Result<Option<usize>> {
+        if rank < self.len {
+            Ok(Some(self.position(Self::u32_at(self.by_count, rank))?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn lower_bound(&self, key: &str) -> Result<usize> {
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.word(mid)?.cmp(key) {
+                Ordering::Less => low = mid + 1,
+                _ => high = mid,
+            }
+        }
+        Ok(low)
+    }
+
+    pub fn find(&self, word: &str) -> Result<Option<usize>> {
+        let i = self.lower_bound(word)?;
+        if i < self.len && self.word(i)? == word {
+            Ok(Some(i))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// All entries starting with `prefix`, ordered by count (descending).
+    pub fn with_prefix(&self, prefix: &str) -> Result<Vec<usize>> {
+        let mut matches = vec![];
+        for i in self.lower_bound(prefix)?..self.len {
+            if !self.word(i)?.starts_with(prefix) {
+                break;
+            }
+            matches.push((self.rank(i)?, i));
+        }
+        matches.sort_unstable();
+        Ok(matches.into_iter().map(|(_, i)| i).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress::Compression;
+
+    fn write_index(path: &str, compression: Option<Compression>) {
+        let mut stats = StatsPackage::new();
+        for (word, count) in [("b", 1), ("a", 3), ("c", 2)] {
+            stats.words_freq.pairs.insert(word.to_string(), count);
+            stats.low_words_freq.pairs.insert(word.to_string(), count);
+        }
+        let target = OutputTarget {
+            path: path.to_string(),
+            no_clobber: false,
+            compression,
+        };
+        write_stats_to_file_index(&mut stats, &target).unwrap();
+    }
+
+    fn check(index: &StatsIndex) {
+        let table = index.table(false);
+        let a = table.find("a").unwrap().unwrap();
+        assert_eq!(
+            (
+                table.word(a).unwrap(),
+                table.count(a),
+                table.rank(a).unwrap()
+            ),
+            ("a", 3, 0)
+        );
+        let c = table.at_rank(1).unwrap().unwrap();
+        assert_eq!(table.word(c).unwrap(), "c");
+        assert_eq!(table.with_prefix("").unwrap(), [0, 2, 1]);
+    }
+
+    #[test]
+    fn mapped_compressed_or_from_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let plain = dir.path().join("stats.idx").to_string_lossy().to_string();
+        let compressed = dir
+            .path()
+            .join("stats.idx.gz")
+            .to_string_lossy()
+            .to_string();
+        write_index(&plain, None);
+        write_index(&compressed, Some(Compression::Gzip));
+
+        let index = StatsIndex::open(&plain).unwrap();
+        assert!(matches!(index.data, IndexData::Mapped(_)));
+        check(&index);
+        let index = StatsIndex::open(&compressed).unwrap();
+        assert!(matches!(index.data, IndexData::Owned(_)));
+        check(&index);
+        check(&StatsIndex::from_bytes(compress::read_file(&compressed).unwrap()).unwrap());
+    }
+
+    // Detected when the corrupted entries are looked up, not when the index is opened.
+    #[test]
+    fn corrupted_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stats.idx").to_string_lossy().to_string();
+        write_index(&path, None);
+        let mut data = compress::read_file(&path).unwrap();
+        let bounds = StatsIndex::from_bytes(data.clone()).unwrap().words_freq;
+        data[bounds.ranks..bounds.ranks + 4].copy_from_slice(&3u32.to_le_bytes());
+        // The first word, "a", isn't valid UTF-8 anymore.
+        data[bounds.words] = 0xff;
+
+        let index = StatsIndex::from_bytes(data).unwrap();
+        let table = index.table(false);
+        assert_eq!(table.count(0), 3);
+        assert!(table.rank(0).is_err());
+        assert_eq!(table.rank(1).unwrap(), 2);
+        assert!(table.word(0).is_err());
+        assert_eq!(table.word(1).unwrap(), "b");
+        assert!(table.find("a").is_err());
+        assert!(index.into_stats().is_err());
+
+        // Truncated tables are detected when opening it.
+        let mut data = compress::read_file(&path).unwrap();
+        data.truncate(bounds.words);
+        assert!(StatsIndex::from_bytes(data).is_err());
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::iter;
+use std::sync::OnceLock;
+
+pub const UNKNOWN_LANGUAGE: &str = "unknown";
+
+// Number of most frequent trigrams compared between a text and a language profile.
+const PROFILE_SIZE: usize = 300;
+// Texts yielding fewer trigrams than this are too short to be classified reliably.
+const MIN_TRIGRAMS: usize = 20;
+// Only the beginning of long articles is looked at, which is plenty for classification.
+const SAMPLE_LIMIT: usize = 4096;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Hebrew,
+    Arabic,
+    Devanagari,
+    Thai,
+    Hangul,
+    Kana,
+    Han,
+    Other,
+}
+
+pub fn script_of(c: char) -> Script {
+    match c as u32 {
+        0x41..=0x5A | 0x61..=0x7A | 0xC0..=0x24F | 0x1E00..=0x1EFF => Script::Latin,
+        0x370..=0x3FF | 0x1F00..=0x1FFF => Script::Greek,
+        0x400..=0x52F => Script::Cyrillic,
+        0x590..=0x5FF => Script::Hebrew,
+        0x600..=0x6FF | 0x750..=0x77F => Script::Arabic,
+        0x900..=0x97F => Script::Devanagari,
+        0xE00..=0xE7F => Script::Thai,
+        0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => Script::Hangul,
+        0x3040..=0x30FF => Script::Kana,
+        0x3400..=0x4DBF | 0x4E00..=0x9FFF => Script::Han,
+        _ => Script::Other,
+    }
+}
+
+// Languages told apart by their trigrams. Each profile is built from the sample text below
+// the first time a language is detected.
+const SAMPLES: &[(&str, Script, &str)] = &[
+    ("en", Script::Latin, "The city is located on the northern bank of the river and has been an important centre of trade since the Middle Ages. Its population grew rapidly during the nineteenth century, when new factories and railways were built. Today it is known for its university, its museums and the old town, which attracts thousands of visitors every year. The climate is mild, with warm summers and cold winters. According to the last census, most of the inhabitants work in services, while the rest are employed in industry and agriculture. The history of the region is closely connected with that of the neighbouring kingdoms, which fought over it for several centuries."),
+    ("de", Script::Latin, "Die Stadt liegt am nördlichen Ufer des Flusses und ist seit dem Mittelalter ein wichtiges Zentrum des Handels. Ihre Bevölkerung wuchs im neunzehnten Jahrhundert sehr schnell, als neue Fabriken und Eisenbahnen gebaut wurden. Heute ist sie für ihre Universität, ihre Museen und die Altstadt bekannt, die jedes Jahr tausende Besucher anzieht. Das Klima ist mild, mit warmen Sommern und kalten Wintern. Nach der letzten Volkszählung arbeiten die meisten Einwohner im Dienstleistungssektor, während die übrigen in der Industrie und in der Landwirtschaft beschäftigt sind. Die Geschichte der Region ist eng mit der Geschichte der benachbarten Königreiche verbunden, die mehrere Jahrhunderte lang um sie kämpften."),
+    ("fr", Script::Latin, "La ville est située sur la rive nord du fleuve et constitue depuis le Moyen Âge un centre important du commerce. Sa population a augmenté rapidement au cours du dix-neuvième siècle, lorsque de nouvelles usines et des chemins de fer ont été construits. Aujourd'hui, elle est connue pour son université, ses musées et la vieille ville, qui attire chaque année des milliers de visiteurs. Le climat est doux, avec des étés chauds et des hivers froids. Selon le dernier recensement, la plupart des habitants travaillent dans les services, tandis que les autres sont employés dans l'industrie et l'agriculture. L'histoire de la région est étroitement liée à celle des royaumes voisins, qui se la sont disputée pendant plusieurs siècles."),
+    ("es", Script::Latin, "La ciudad está situada en la orilla norte del río y ha sido un importante centro de comercio desde la Edad Media. Su población creció rápidamente durante el siglo diecinueve, cuando se construyeron nuevas fábricas y ferrocarriles. Hoy en día es conocida por su universidad, sus museos y el casco antiguo, que atrae a miles de visitantes cada año. El clima es templado, con veranos cálidos e inviernos fríos. Según el último censo, la mayoría de los habitantes trabaja en los servicios, mientras que los demás están empleados en la industria y la agricultura. La historia de la región está estrechamente relacionada con la de los reinos vecinos, que lucharon por ella durante varios siglos."),
+    ("it", Script::Latin, "La città si trova sulla riva settentrionale del fiume ed è stata un importante centro di commercio fin dal Medioevo. La sua popolazione crebbe rapidamente durante il diciannovesimo secolo, quando furono costruite nuove fabbriche e ferrovie. Oggi è conosciuta per la sua università, i suoi musei e il centro storico, che attira ogni anno migliaia di visitatori. Il clima è mite, con estati calde e inverni freddi. Secondo l'ultimo censimento, la maggior parte degli abitanti lavora nei servizi, mentre gli altri sono impiegati nell'industria e nell'agricoltura. La storia della regione è strettamente legata a quella dei regni vicini, che se la contesero per diversi secoli."),
+    ("pt", Script::Latin, "A cidade está situada na margem norte do rio e tem sido um importante centro de comércio desde a Idade Média. A sua população cresceu rapidamente durante o século dezanove, quando foram construídas novas fábricas e ferrovias. Hoje é conhecida pela sua universidade, pelos seus museus e pelo centro histórico, que atrai milhares de visitantes todos os anos. O clima é ameno, com verões quentes e invernos frios. Segundo o último recenseamento, a maioria dos habitantes trabalha nos serviços, enquanto os restantes estão empregados na indústria e na agricultura. A história da região está intimamente ligada à dos reinos vizinhos, que a disputaram durante vários séculos."),
+    ("nl", Script::Latin, "De stad ligt aan de noordelijke oever van de rivier en is sinds de middeleeuwen een belangrijk centrum van handel. De bevolking groeide snel in de negentiende eeuw, toen er nieuwe fabrieken en spoorwegen werden gebouwd. Tegenwoordig is de stad bekend om haar universiteit, haar musea en de oude binnenstad, die elk jaar duizenden bezoekers trekt. Het klimaat is mild, met warme zomers en koude winters. Volgens de laatste volkstelling werken de meeste inwoners in de dienstensector, terwijl de anderen in de industrie en de landbouw werkzaam zijn. De geschiedenis van de regio is nauw verbonden met die van de naburige koninkrijken, die er eeuwenlang om hebben gevochten."),
+    ("ro", Script::Latin, "Orașul este situat pe malul nordic al râului și a fost un important centru comercial încă din Evul Mediu. Populația sa a crescut rapid în secolul al nouăsprezecelea, când au fost construite noi fabrici și căi ferate. Astăzi este cunoscut pentru universitatea, muzeele și centrul vechi, care atrage în fiecare an mii de vizitatori. Clima este blândă, cu veri calde și ierni reci. Conform ultimului recensământ, majoritatea locuitorilor lucrează în servicii, în timp ce ceilalți sunt angajați în industrie și agricultură. Istoria regiunii este strâns legată de cea a regatelor vecine, care s-au luptat pentru ea timp de mai multe secole."),
+    ("ru", Script::Cyrillic, "Город расположен на северном берегу реки и со времён Средневековья является важным центром торговли. Его население быстро росло в девятнадцатом веке, когда были построены новые фабрики и железные дороги. Сегодня он известен своим университетом, музеями и старым городом, который каждый год привлекает тысячи посетителей. Климат мягкий, с тёплым летом и холодной зимой. По данным последней переписи, большинство жителей работает в сфере услуг, а остальные заняты в промышленности и сельском хозяйстве. История региона тесно связана с историей соседних королевств, которые в течение нескольких веков боролись за него."),
+    ("uk", Script::Cyrillic, "Місто розташоване на північному березі річки і з часів Середньовіччя є важливим центром торгівлі. Його населення швидко зростало в дев'ятнадцятому столітті, коли були збудовані нові фабрики та залізниці. Сьогодні воно відоме своїм університетом, музеями та старим містом, яке щороку приваблює тисячі відвідувачів. Клімат м'який, з теплим літом і холодною зимою. За даними останнього перепису, більшість мешканців працює у сфері послуг, а решта зайнята в промисловості та сільському господарстві. Історія регіону тісно пов'язана з історією сусідніх королівств, які протягом кількох століть боролися за нього."),
+];
+
+struct LanguageProfile {
+    language: &'static str,
+    script: Script,
+    ranks: HashMap<String, usize>,
+}
+
+fn profiles() -> &'static [LanguageProfile] {
+    static PROFILES: OnceLock<Vec<LanguageProfile>> = OnceLock::new();
+    PROFILES.get_or_init(|| {
+        SAMPLES
+            .iter()
+            .map(|&(language, script, sample)| LanguageProfile {
+                language,
+                script,
+                ranks: trigram_ranking(sample)
+                    .into_iter()
+                    .enumerate()
+                    .map(|(rank, trigram)| (trigram, rank))
+                    .collect(),
+            })
+            .collect()
+    })
+}
+
+// The PROFILE_SIZE most frequent trigrams of the text's lowercased words, each word padded
+// with spaces so that word beginnings & endings are captured too.
+fn trigram_ranking(text: &str) -> Vec<String> {
+    let mut counts: HashMap<String, u32> = HashMap::new();
+    for word in text.split(|c: char| !c.is_alphabetic()) {
+        if word.is_empty() {
+            continue;
+        }
+        let padded: Vec<char> = iter::once(' ')
+            .chain(word.chars().flat_map(char::to_lowercase))
+            .chain(iter::once(' '))
+            .collect();
+        for trigram in padded.windows(3) {
+            *counts.entry(trigram.iter().collect()).or_insert(0) += 1;
+        }
+    }
+    let mut ranking: Vec<(String, u32)> = counts.into_iter().collect();
+    ranking.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranking
+        .into_iter()
+        .take(PROFILE_SIZE)
+        .map(|(trigram, _)| trigram)
+        .collect()
+}
+
+fn sample_of(text: &str) -> &str {
+    if text.len() <= SAMPLE_LIMIT {
+        return text;
+    }
+    let mut end = SAMPLE_LIMIT;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+fn dominant_script(text: &str) -> Option<(Script, HashMap<Script, usize>)> {
+    let mut counts: HashMap<Script, usize> = HashMap::new();
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        *counts.entry(script_of(c)).or_insert(0) += 1;
+    }
+    let script = counts
+        .iter()
+        .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+        .map(|(&script, _)| script)?;
+    Some((script, counts))
+}
+
+// Returns an ISO 639-1 code, or UNKNOWN_LANGUAGE. Scripts used by a single language decide
+// on their own; Latin & Cyrillic texts are compared against the trigram profiles using
+// the "out-of-place" distance between rankings.
+pub fn detect_language(text: &str) -> &'static str {
+    let sample = sample_of(text);
+    let (script, script_counts) = match dominant_script(sample) {
+        Some(dominant) => dominant,
+        None => return UNKNOWN_LANGUAGE,
+    };
+    match script {
+        Script::Greek => return "el",
+        Script::Hebrew => return "he",
+        Script::Arabic => return "ar",
+        Script::Devanagari => return "hi",
+        Script::Thai => return "th",
+        Script::Hangul => return "ko",
+        Script::Kana => return "ja",
+        Script::Han if script_counts.contains_key(&Script::Kana) => return "ja",
+        Script::Han => return "zh",
+        Script::Other => return UNKNOWN_LANGUAGE,
+        Script::Latin | Script::Cyrillic => {}
+    }
+
+    let ranking = trigram_ranking(sample);
+    if ranking.len() < MIN_TRIGRAMS {
+        return UNKNOWN_LANGUAGE;
+    }
+    profiles()
+        .iter()
+        .filter(|profile| profile.script == script)
+        .map(|profile| {
+            let distance: usize = ranking
+                .iter()
+                .enumerate()
+                .map(|(rank, trigram)| match profile.ranks.get(trigram) {
+                    Some(&profile_rank) => profile_rank.abs_diff(rank),
+                    None => PROFILE_SIZE,
+                })
+                .sum();
+            (profile.language, distance)
+        })
+        .min_by_key(|&(_, distance)| distance)
+        .map_or(UNKNOWN_LANGUAGE, |(language, _)| language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_texts_in_each_script() {
+        assert_eq!(
+            detect_language("The river flows through the old town, where most of the houses were built during the last century."),
+            "en"
+        );
+        assert_eq!(
+            detect_language("Der Fluss fließt durch die Altstadt, in der die meisten Häuser im letzten Jahrhundert gebaut wurden."),
+            "de"
+        );
+        assert_eq!(
+            detect_language("Le fleuve traverse la vieille ville, où la plupart des maisons ont été construites au siècle dernier."),
+            "fr"
+        );
+        assert_eq!(
+            detect_language("Река протекает через старый город, где большинство домов было построено в прошлом веке."),
+            "ru"
+        );
+        assert_eq!(
+            detect_language("这条河流经老城区，大部分房屋建于上个世纪。"),
+            "zh"
+        );
+        assert_eq!(detect_language("川は旧市街を流れています。"), "ja");
+    }
+
+    #[test]
+    fn too_short_or_unclassifiable_texts() {
+        assert_eq!(detect_language("The river"), UNKNOWN_LANGUAGE);
+        assert_eq!(detect_language(""), UNKNOWN_LANGUAGE);
+        assert_eq!(detect_language("1984 — 2024 (+/- 3%)"), UNKNOWN_LANGUAGE);
+    }
+}
@@ -0,0 +1,1556 @@
+use anyhow::{bail, Context, Result};
+use clap::{CommandFactory, FromArgMatches, ValueEnum};
+use serde_derive::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, debug_span, field, info};
+
+mod archive;
+mod chars;
+mod cli;
+mod compress;
+mod config;
+mod cooccur;
+mod dedup;
+mod diff;
+mod distribution;
+mod index;
+mod lang;
+mod logging;
+mod markup;
+pub mod output;
+mod per_article;
+mod reader;
+mod serve;
+mod sketch;
+mod spill;
+mod sqlite;
+mod tsv;
+mod watch;
+
+#[derive(Deserialize)]
+pub struct Article {
+    id: String,
+    text: String,
+    title: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LongestItem {
+    title: String,
+    path: String,
+    size: usize,
+}
+
+impl Default for LongestItem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LongestItem {
+    pub fn new() -> Self {
+        Self {
+            title: String::from(""),
+            path: String::from(""),
+            size: 0,
+        }
+    }
+    pub fn new_longest_article(a: &Article, p: &str) -> Self {
+        let mut full_path: String = p.to_string();
+        full_path.push('/');
+        full_path.push_str(a.id.as_str());
+        Self {
+            title: a.title.clone(),
+            path: full_path,
+            size: a.text.len(),
+        }
+    }
+    pub fn new_longest_title(a: &Article, p: &str) -> Self {
+        let mut full_path: String = p.to_string();
+        full_path.push('/');
+        full_path.push_str(a.id.as_str());
+        Self {
+            title: a.title.clone(),
+            path: full_path,
+            size: a.title.len(),
+        }
+    }
+
+    // Ties go to the smallest path, so that the result doesn't depend on the merging order.
+    fn is_longer_than(&self, other: &Self) -> bool {
+        (self.size, &other.path) > (other.size, &self.path)
+    }
+}
+
+pub fn split_words(s: &str) -> impl Iterator<Item = &str> {
+    s.split(|c: char| -> bool { !c.is_alphanumeric() && c != '\'' })
+        .filter(|word| !word.is_empty())
+}
+
+#[derive(Clone)]
+pub struct WordsFrequencyMap {
+    pairs: HashMap<String, u32>,
+}
+
+impl WordsFrequencyMap {
+    pub fn new() -> Self {
+        Self {
+            pairs: HashMap::new(),
+        }
+    }
+
+    // A list left out (None) is not built at all.
+    pub fn map_words(mut normal: Option<&mut Self>, mut lowercase: Option<&mut Self>, s: &str) {
+        for word in split_words(s) {
+            if let Some(normal) = &mut normal {
+                normal
+                    .pairs
+                    .entry(word.to_string())
+                    .and_modify(|count| *count += 1)
+                    .or_insert(1);
+            }
+            if let Some(lowercase) = &mut lowercase {
+                lowercase
+                    .pairs
+                    .entry(word.to_ascii_lowercase())
+                    .and_modify(|count| *count += 1)
+                    .or_insert(1);
+            }
+        }
+    }
+    pub fn map_article(
+        mut normal: Option<&mut Self>,
+        mut lowercase: Option<&mut Self>,
+        a: &Article,
+    ) {
+        WordsFrequencyMap::map_words(normal.as_deref_mut(), lowercase.as_deref_mut(), &a.title);
+        WordsFrequencyMap::map_words(normal, lowercase, &a.text);
+    }
+
+    pub fn merge_with(&mut self, other: Self) {
+        for (key, value) in other.pairs {
+            self.pairs
+                .entry(key)
+                .and_modify(|count| *count += value)
+                .or_insert(value);
+        }
+    }
+
+    // Sorted alphabetically, like the spilled words, so that the output doesn't depend on the
+    // hashing.
+    pub fn sorted_pairs(&self) -> Vec<(&str, u32)> {
+        let mut pairs: Vec<(&str, u32)> = self
+            .pairs
+            .iter()
+            .map(|(word, &count)| (word.as_str(), count))
+            .collect();
+        pairs.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        pairs
+    }
+
+    pub fn sorted_words(&self) -> spill::WordsIter<'_> {
+        Box::new(
+            self.sorted_pairs()
+                .into_iter()
+                .map(|(word, count)| Ok((word.to_string(), count))),
+        )
+    }
+}
+
+impl Default for WordsFrequencyMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WordFreq {
+    word: String,
+    appearances: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LanguageArticles {
+    language: String,
+    articles: u32,
+}
+
+// Same layout as serde_json's pretty printer, but without collecting the words first.
+fn write_words_json(w: impl Write, words: spill::WordsIter) -> Result<()> {
+    let mut w = BufWriter::new(w);
+    let mut words = words.peekable();
+    if words.peek().is_none() {
+        w.write_all(b"[]")?;
+        w.flush()?;
+        return Ok(());
+    }
+    w.write_all(b"[")?;
+    let mut first = true;
+    for pair in words {
+        let (word, count) = pair?;
+        if !first {
+            w.write_all(b",")?;
+        }
+        first = false;
+        write!(
+            w,
+            "\n  {{\n    \"word\": {},\n    \"appearances\": {}\n  }}",
+            serde_json::to_string(&word)?,
+            count
+        )?;
+    }
+    w.write_all(b"\n]")?;
+    w.flush()?;
+    Ok(())
+}
+
+pub fn write_stats_to_file(
+    stats: &mut StatsPackage,
+    sections: &[Section],
+    target: &output::OutputTarget,
+) -> Result<()> {
+    let stats_file_writer = RefCell::new(output::OutputFile::create(target)?);
+
+    // The JSON values don't end with a newline, so every header but the first starts with one.
+    // Sections of options that weren't enabled aren't written at all.
+    let mut separator = "";
+    for section in sections {
+        match section {
+            Section::Words => {
+                writeln!(
+                    stats_file_writer.borrow_mut(),
+                    "{}\tWords frequency (as written)",
+                    separator
+                )?;
+                write_words_json(&mut *stats_file_writer.borrow_mut(), stats.words(false)?)?;
+            }
+            Section::Lower => {
+                writeln!(
+                    stats_file_writer.borrow_mut(),
+                    "{}\tWords frequency (lowercase)",
+                    separator
+                )?;
+                write_words_json(&mut *stats_file_writer.borrow_mut(), stats.words(true)?)?;
+            }
+            Section::LongestArticle => {
+                writeln!(
+                    stats_file_writer.borrow_mut(),
+                    "{}\tLongest article",
+                    separator
+                )?;
+                serde_json::to_writer_pretty(
+                    &mut *stats_file_writer.borrow_mut(),
+                    &stats.long_art,
+                )?;
+            }
+            Section::LongestTitle => {
+                writeln!(
+                    stats_file_writer.borrow_mut(),
+                    "{}\tLongest title",
+                    separator
+                )?;
+                serde_json::to_writer_pretty(
+                    &mut *stats_file_writer.borrow_mut(),
+                    &stats.long_title,
+                )?;
+            }
+            Section::Distribution => {
+                let Some(distribution) = &stats.distribution else {
+                    continue;
+                };
+                writeln!(
+                    stats_file_writer.borrow_mut(),
+                    "{}\tDistribution",
+                    separator
+                )?;
+                serde_json::to_writer_pretty(&mut *stats_file_writer.borrow_mut(), distribution)?;
+            }
+            Section::Approx => {
+                let Some(summary) = &stats.approx_summary else {
+                    continue;
+                };
+                writeln!(
+                    stats_file_writer.borrow_mut(),
+                    "{}\tApproximate counting",
+                    separator
+                )?;
+                serde_json::to_writer_pretty(&mut *stats_file_writer.borrow_mut(), summary)?;
+
+                stats_file_writer
+                    .borrow_mut()
+                    .write_all("\n\tError bounds (as written)\n".as_bytes())?;
+                serde_json::to_writer_pretty(
+                    &mut *stats_file_writer.borrow_mut(),
+                    &summary.words.bounds,
+                )?;
+
+                stats_file_writer
+                    .borrow_mut()
+                    .write_all("\n\tError bounds (lowercase)\n".as_bytes())?;
+                serde_json::to_writer_pretty(
+                    &mut *stats_file_writer.borrow_mut(),
+                    &summary.low_words.bounds,
+                )?;
+            }
+            Section::Languages => {
+                if stats.languages.is_empty() {
+                    continue;
+                }
+                let languages = stats.sorted_languages();
+                let articles_vec: Vec<LanguageArticles> = languages
+                    .iter()
+                    .map(|(language, words)| LanguageArticles {
+                        language: language.to_string(),
+                        articles: words.articles,
+                    })
+                    .collect();
+                writeln!(stats_file_writer.borrow_mut(), "{}\tLanguages", separator)?;
+                serde_json::to_writer_pretty(&mut *stats_file_writer.borrow_mut(), &articles_vec)?;
+
+                for (language, words) in languages {
+                    write!(
+                        stats_file_writer.borrow_mut(),
+                        "\n\tWords frequency (as written, {})\n",
+                        language
+                    )?;
+                    write_words_json(
+                        &mut *stats_file_writer.borrow_mut(),
+                        words.words_freq.sorted_words(),
+                    )?;
+
+                    write!(
+                        stats_file_writer.borrow_mut(),
+                        "\n\tWords frequency (lowercase, {})\n",
+                        language
+                    )?;
+                    write_words_json(
+                        &mut *stats_file_writer.borrow_mut(),
+                        words.low_words_freq.sorted_words(),
+                    )?;
+                }
+            }
+            Section::Cooccur => {
+                let Some(cooccurrences) = &stats.cooccur_summary else {
+                    continue;
+                };
+                writeln!(
+                    stats_file_writer.borrow_mut(),
+                    "{}\tCo-occurrences",
+                    separator
+                )?;
+                serde_json::to_writer_pretty(&mut *stats_file_writer.borrow_mut(), cooccurrences)?;
+            }
+            Section::Chars => {
+                let Some(char_stats) = &stats.char_stats else {
+                    continue;
+                };
+                let summary = char_stats.summary();
+                writeln!(stats_file_writer.borrow_mut(), "{}\tCharacters", separator)?;
+                serde_json::to_writer_pretty(&mut *stats_file_writer.borrow_mut(), &summary)?;
+
+                stats_file_writer
+                    .borrow_mut()
+                    .write_all("\n\tScripts\n".as_bytes())?;
+                serde_json::to_writer_pretty(
+                    &mut *stats_file_writer.borrow_mut(),
+                    &summary.scripts,
+                )?;
+
+                stats_file_writer
+                    .borrow_mut()
+                    .write_all("\n\tCode points\n".as_bytes())?;
+                serde_json::to_writer_pretty(
+                    &mut *stats_file_writer.borrow_mut(),
+                    &summary.code_points,
+                )?;
+
+                stats_file_writer
+                    .borrow_mut()
+                    .write_all("\n\tBytes\n".as_bytes())?;
+                serde_json::to_writer_pretty(&mut *stats_file_writer.borrow_mut(), &summary.bytes)?;
+            }
+            Section::Duplicates => {
+                let Some(report) = &stats.dedup_report else {
+                    continue;
+                };
+                writeln!(stats_file_writer.borrow_mut(), "{}\tDuplicates", separator)?;
+                serde_json::to_writer_pretty(&mut *stats_file_writer.borrow_mut(), report)?;
+
+                stats_file_writer
+                    .borrow_mut()
+                    .write_all("\n\tDuplicate clusters\n".as_bytes())?;
+                serde_json::to_writer_pretty(
+                    &mut *stats_file_writer.borrow_mut(),
+                    &report.clusters,
+                )?;
+            }
+        }
+        separator = "\n";
+    }
+
+    stats_file_writer.into_inner().finish()
+}
+
+pub fn write_stats_to_file_plain(
+    stats: &mut StatsPackage,
+    sections: &[Section],
+    target: &output::OutputTarget,
+) -> Result<()> {
+    let mut stats_file = BufWriter::new(output::OutputFile::create(target)?);
+
+    for section in sections {
+        match section {
+            Section::Words => {
+                writeln!(stats_file, "\tWords frequency (as written)\n")?;
+                for pair in stats.words(false)? {
+                    let (word, count) = pair?;
+                    writeln!(stats_file, "{}: {}", word, count)?;
+                }
+            }
+            Section::Lower => {
+                writeln!(stats_file, "\tWords frequency (lowercase)\n")?;
+                for pair in stats.words(true)? {
+                    let (word, count) = pair?;
+                    writeln!(stats_file, "{}: {}", word, count)?;
+                }
+            }
+            Section::LongestArticle => {
+                writeln!(stats_file, "\tLongest article\n")?;
+                writeln!(
+                    stats_file,
+                    "Title: {}\nPath: {}\nSize: {}",
+                    stats.long_art.title, stats.long_art.path, stats.long_art.size
+                )?;
+            }
+            Section::LongestTitle => {
+                writeln!(stats_file, "\tLongest title\n")?;
+                writeln!(
+                    stats_file,
+                    "Title: {}\nPath: {}\nSize: {}",
+                    stats.long_title.title, stats.long_title.path, stats.long_title.size
+                )?;
+            }
+            Section::Distribution => {
+                let Some(distribution) = &stats.distribution else {
+                    continue;
+                };
+                writeln!(stats_file, "\tDistribution\n")?;
+                match (distribution.zipf_exponent, distribution.zipf_r_squared) {
+                    (Some(exponent), Some(r_squared)) => writeln!(
+                        stats_file,
+                        "Zipf exponent: {:.4}\nZipf fit R^2: {:.4} ({} ranks fitted)",
+                        exponent, r_squared, distribution.zipf_ranks_fitted
+                    )?,
+                    _ => writeln!(stats_file, "Zipf exponent: not enough words to fit")?,
+                }
+                for length in &distribution.word_lengths {
+                    writeln!(
+                        stats_file,
+                        "Length {}: {} occurrences, {} distinct words",
+                        length.length, length.occurrences, length.distinct_words
+                    )?;
+                }
+            }
+            Section::Approx => {
+                let Some(summary) = &stats.approx_summary else {
+                    continue;
+                };
+                writeln!(stats_file, "\tApproximate counting\n")?;
+                writeln!(stats_file, "Top words: {}", summary.top_k)?;
+                for (label, section) in [
+                    ("as written", &summary.words),
+                    ("lowercase", &summary.low_words),
+                ] {
+                    writeln!(
+                        stats_file,
+                        "Total words ({}): {}\nDistinct words ({}, estimated): {}\nMaximum overestimate ({}): {} (with {:.2}% confidence)",
+                        label,
+                        section.total_words,
+                        label,
+                        section.distinct_words_estimate,
+                        label,
+                        section.max_overestimate,
+                        section.confidence * 100.0
+                    )?;
+                }
+
+                writeln!(stats_file, "\tError bounds (as written)\n")?;
+                for bounds in &summary.words.bounds {
+                    writeln!(
+                        stats_file,
+                        "{}: {}..{}",
+                        bounds.word, bounds.min_appearances, bounds.max_appearances
+                    )?;
+                }
+
+                writeln!(stats_file, "\tError bounds (lowercase)\n")?;
+                for bounds in &summary.low_words.bounds {
+                    writeln!(
+                        stats_file,
+                        "{}: {}..{}",
+                        bounds.word, bounds.min_appearances, bounds.max_appearances
+                    )?;
+                }
+            }
+            Section::Languages => {
+                if stats.languages.is_empty() {
+                    continue;
+                }
+                let languages = stats.sorted_languages();
+
+                writeln!(stats_file, "\tLanguages\n")?;
+                for (language, words) in &languages {
+                    writeln!(stats_file, "{}: {}", language, words.articles)?;
+                }
+
+                for (language, words) in languages {
+                    writeln!(stats_file, "\tWords frequency (as written, {})\n", language)?;
+                    for (word, count) in words.words_freq.sorted_pairs() {
+                        writeln!(stats_file, "{}: {}", word, count)?;
+                    }
+
+                    writeln!(stats_file, "\tWords frequency (lowercase, {})\n", language)?;
+                    for (word, count) in words.low_words_freq.sorted_pairs() {
+                        writeln!(stats_file, "{}: {}", word, count)?;
+                    }
+                }
+            }
+            Section::Cooccur => {
+                let Some(cooccurrences) = &stats.cooccur_summary else {
+                    continue;
+                };
+                for target in cooccurrences {
+                    writeln!(
+                        stats_file,
+                        "\tCo-occurrences ({}, {} appearances)\n",
+                        target.target, target.appearances
+                    )?;
+                    for neighbor in &target.neighbors {
+                        writeln!(
+                            stats_file,
+                            "{}: {} (PMI: {:.3})",
+                            neighbor.word, neighbor.count, neighbor.pmi
+                        )?;
+                    }
+                }
+            }
+            Section::Chars => {
+                let Some(char_stats) = &stats.char_stats else {
+                    continue;
+                };
+                let summary = char_stats.summary();
+                writeln!(stats_file, "\tCharacters\n")?;
+                writeln!(
+                    stats_file,
+                    "Total characters: {}\nDistinct characters: {}\nControl characters: {}\nReplacement characters (U+FFFD): {}\nTotal bytes: {}",
+                    summary.total_characters,
+                    summary.distinct_characters,
+                    summary.control_characters,
+                    summary.replacement_characters,
+                    summary.total_bytes
+                )?;
+
+                writeln!(stats_file, "\tScripts\n")?;
+                for script in &summary.scripts {
+                    writeln!(stats_file, "{}: {}", script.script, script.appearances)?;
+                }
+
+                writeln!(stats_file, "\tCode points\n")?;
+                for code_point in &summary.code_points {
+                    writeln!(
+                        stats_file,
+                        "{} '{}': {}",
+                        code_point.code_point,
+                        code_point.character.escape_debug(),
+                        code_point.appearances
+                    )?;
+                }
+
+                writeln!(stats_file, "\tBytes\n")?;
+                for byte in &summary.bytes {
+                    writeln!(stats_file, "{}: {}", byte.byte, byte.appearances)?;
+                }
+            }
+            Section::Duplicates => {
+                let Some(report) = &stats.dedup_report else {
+                    continue;
+                };
+                writeln!(stats_file, "\tDuplicates\n")?;
+                writeln!(
+                    stats_file,
+                    "Articles checked: {}\nExact duplicates: {}\nNear duplicates (similarity >= {}): {}\nExcluded from counts: {}",
+                    report.articles_checked,
+                    report.exact_duplicates,
+                    report.threshold,
+                    report.near_duplicates,
+                    if report.excluded_from_counts { "yes" } else { "no" }
+                )?;
+
+                writeln!(stats_file, "\tDuplicate clusters\n")?;
+                for cluster in &report.clusters {
+                    let duplicates: Vec<String> = cluster
+                        .duplicates
+                        .iter()
+                        .map(|duplicate| {
+                            if duplicate.exact {
+                                format!("{} (exact)", duplicate.path)
+                            } else {
+                                format!("{} ({:.2})", duplicate.path, duplicate.similarity)
+                            }
+                        })
+                        .collect();
+                    writeln!(stats_file, "{}: {}", cluster.article, duplicates.join(", "))?;
+                }
+            }
+        }
+    }
+
+    stats_file
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .finish()
+}
+
+#[derive(Default)]
+pub struct RunInfo {
+    input: String,
+    input_size: u64,
+    files_total: usize,
+    files_processed: usize,
+    started_at: u64,
+    processing_millis: u128,
+}
+
+#[derive(Clone, Default)]
+pub struct AnalysisOptions {
+    detect_languages: bool,
+    strip_markup: markup::MarkupMode,
+    approx: Option<sketch::ApproxConfig>,
+    spill: Option<spill::SpillConfig>,
+    cooccur: Option<cooccur::CooccurConfig>,
+    char_stats: bool,
+    dedup: Option<dedup::DedupConfig>,
+    skip_words: bool,
+    skip_lower: bool,
+    skip_longest_article: bool,
+    skip_longest_title: bool,
+    skip_distribution: bool,
+    per_article: Option<output::OutputTarget>,
+}
+
+#[derive(Clone, Default)]
+pub struct LanguageWords {
+    articles: u32,
+    words_freq: WordsFrequencyMap,
+    low_words_freq: WordsFrequencyMap,
+}
+
+impl LanguageWords {
+    pub fn merge_with(&mut self, other: Self) {
+        self.articles += other.articles;
+        self.words_freq.merge_with(other.words_freq);
+        self.low_words_freq.merge_with(other.low_words_freq);
+    }
+}
+
+pub struct StatsPackage {
+    words_freq: WordsFrequencyMap,
+    low_words_freq: WordsFrequencyMap,
+    long_art: LongestItem,
+    long_title: LongestItem,
+    languages: HashMap<String, LanguageWords>,
+    approx: Option<sketch::ApproxStats>,
+    approx_summary: Option<sketch::ApproxSummary>,
+    cooccur: Option<cooccur::CooccurStats>,
+    cooccur_summary: Option<Vec<cooccur::TargetCooccurrences>>,
+    char_stats: Option<chars::CharStats>,
+    dedup_report: Option<dedup::DedupReport>,
+    distribution: Option<distribution::Distribution>,
+    spill: Option<spill::SpillConfig>,
+    words_runs: Vec<PathBuf>,
+    low_words_runs: Vec<PathBuf>,
+    article_rows: Option<per_article::ArticleRows>,
+}
+
+impl Default for StatsPackage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatsPackage {
+    pub fn new() -> Self {
+        Self {
+            words_freq: WordsFrequencyMap::new(),
+            low_words_freq: WordsFrequencyMap::new(),
+            long_art: LongestItem::new(),
+            long_title: LongestItem::new(),
+            languages: HashMap::new(),
+            approx: None,
+            approx_summary: None,
+            cooccur: None,
+            cooccur_summary: None,
+            char_stats: None,
+            dedup_report: None,
+            distribution: None,
+            spill: None,
+            words_runs: vec![],
+            low_words_runs: vec![],
+            article_rows: None,
+        }
+    }
+
+    pub fn sorted_languages(&self) -> Vec<(&str, &LanguageWords)> {
+        let mut languages: Vec<(&str, &LanguageWords)> = self
+            .languages
+            .iter()
+            .map(|(language, words)| (language.as_str(), words))
+            .collect();
+        languages.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        languages
+    }
+
+    // Computes the PMI scores, which need the merged lowercase frequency list.
+    pub fn finish_cooccur(&mut self) {
+        if let Some(cooccur) = self.cooccur.take() {
+            self.cooccur_summary = Some(cooccur.finish(&self.low_words_freq));
+        }
+    }
+
+    fn spill_words(&mut self) -> Result<()> {
+        if let Some(spill) = &self.spill {
+            self.words_runs.push(spill.write_run(&mut self.words_freq)?);
+            self.low_words_runs
+                .push(spill.write_run(&mut self.low_words_freq)?);
+        }
+        Ok(())
+    }
+
+    // Moves the words to sorted runs on disk once the maps outgrow their share of the threshold.
+    pub fn spill_if_needed(&mut self) -> Result<()> {
+        if let Some(spill) = &self.spill {
+            if spill.is_over_threshold(&self.words_freq, &self.low_words_freq) {
+                self.spill_words()?;
+            }
+        }
+        Ok(())
+    }
+
+    // If anything was spilled, the words left in memory join the runs, so that the writers
+    // only have to read the merged runs.
+    pub fn finish_spill(&mut self) -> Result<()> {
+        if !self.words_runs.is_empty() || !self.low_words_runs.is_empty() {
+            self.spill_words()?;
+        }
+        Ok(())
+    }
+
+    // Word lengths & Zipf fit of the final as-written (or lowercase, if the as-written one
+    // wasn't built) list, which the writers output later.
+    // Not computed for approximate counts, which only keep the top words.
+    pub fn compute_distribution(&mut self, lowercase: bool) -> Result<()> {
+        if self.approx_summary.is_some() {
+            return Ok(());
+        }
+        let (words, runs) = if lowercase {
+            (&self.low_words_freq, &mut self.low_words_runs)
+        } else {
+            (&self.words_freq, &mut self.words_runs)
+        };
+        let distribution = match &self.spill {
+            Some(spill) if !runs.is_empty() => {
+                *runs = spill.compact_runs(std::mem::take(runs))?;
+                distribution::Distribution::from_words(
+                    spill::read_runs(runs)?
+                        .map(|pair| pair.map(|(word, count)| (word.chars().count(), count))),
+                )?
+            }
+            _ => distribution::Distribution::from_words(
+                words
+                    .pairs
+                    .iter()
+                    .map(|(word, &count)| Ok((word.chars().count(), count))),
+            )?,
+        };
+        self.distribution = Some(distribution);
+        Ok(())
+    }
+
+    // Sorted alphabetically: spilled words come merged & sorted from the runs (compacted first,
+    // hence the `&mut`), the others are sorted in memory. The lists are left in place, so that
+    // 'watch' can write its cumulative stats without copying them.
+    pub fn sorted_words(&mut self, lowercase: bool) -> Result<spill::SortedWords<'_>> {
+        let (words, runs) = if lowercase {
+            (&self.low_words_freq, &mut self.low_words_runs)
+        } else {
+            (&self.words_freq, &mut self.words_runs)
+        };
+        match &self.spill {
+            Some(spill) if !runs.is_empty() => {
+                *runs = spill.compact_runs(std::mem::take(runs))?;
+                Ok(spill::SortedWords::Runs(runs))
+            }
+            _ => Ok(spill::SortedWords::Memory(words.sorted_pairs())),
+        }
+    }
+
+    pub fn words(&mut self, lowercase: bool) -> Result<spill::WordsIter<'_>> {
+        self.sorted_words(lowercase)?.into_words()
+    }
+
+    // Replaces the sketches with the estimated counts of the top words, so that the writers
+    // can output them like exact counts.
+    pub fn finish_approx(&mut self, config: &sketch::ApproxConfig) {
+        if let Some(approx) = self.approx.take() {
+            let (words_freq, low_words_freq, summary) = approx.finish(config);
+            self.words_freq = words_freq;
+            self.low_words_freq = low_words_freq;
+            self.approx_summary = Some(summary);
+        }
+    }
+
+    pub fn merge_with(&mut self, other: Self) {
+        self.words_freq.merge_with(other.words_freq);
+        self.low_words_freq.merge_with(other.low_words_freq);
+        self.words_runs.extend(other.words_runs);
+        self.low_words_runs.extend(other.low_words_runs);
+        if self.spill.is_none() {
+            self.spill = other.spill;
+        }
+        for (language, words) in other.languages {
+            self.languages
+                .entry(language)
+                .or_default()
+                .merge_with(words);
+        }
+        match (&mut self.approx, other.approx) {
+            (Some(approx), Some(other_approx)) => approx.merge_with(other_approx),
+            (None, Some(other_approx)) => self.approx = Some(other_approx),
+            (_, None) => {}
+        }
+        match (&mut self.cooccur, other.cooccur) {
+            (Some(cooccur), Some(other_cooccur)) => cooccur.merge_with(other_cooccur),
+            (None, Some(other_cooccur)) => self.cooccur = Some(other_cooccur),
+            (_, None) => {}
+        }
+        match (&mut self.char_stats, other.char_stats) {
+            (Some(char_stats), Some(other_char_stats)) => char_stats.merge_with(other_char_stats),
+            (None, Some(other_char_stats)) => self.char_stats = Some(other_char_stats),
+            (_, None) => {}
+        }
+        if other.long_art.is_longer_than(&self.long_art) {
+            self.long_art = other.long_art;
+        }
+        if other.long_title.is_longer_than(&self.long_title) {
+            self.long_title = other.long_title;
+        }
+    }
+}
+
+pub fn process_file(
+    data: String,
+    path: String,
+    file_index: usize,
+    options: AnalysisOptions,
+) -> Result<StatsPackage> {
+    let approx = options.approx.as_ref().map(sketch::ApproxStats::new);
+    process_file_with_sketch(data, path, file_index, options, approx)
+}
+
+// Counts into the given sketch (--approx), which 'analyze' reuses for all the files of a worker
+// rather than allocating a full one per file.
+fn process_file_with_sketch(
+    data: String,
+    path: String,
+    file_index: usize,
+    options: AnalysisOptions,
+    approx: Option<sketch::ApproxStats>,
+) -> Result<StatsPackage> {
+    let mut stats = StatsPackage::new();
+    stats.approx = approx;
+    if let Some(config) = &options.cooccur {
+        stats.cooccur = Some(cooccur::CooccurStats::new(config));
+    }
+    if options.char_stats {
+        stats.char_stats = Some(chars::CharStats::new());
+    }
+    stats.spill = options.spill.clone();
+    if options.per_article.is_some() {
+        stats.article_rows = Some(per_article::ArticleRows::new()?);
+    }
+    let mut articles_vec: Vec<Article> = serde_json::from_str(&data)?;
+    tracing::Span::current().record("articles", articles_vec.len());
+    if options.strip_markup != markup::MarkupMode::None {
+        for art in &mut articles_vec {
+            art.text = markup::strip_markup(&art.text, options.strip_markup);
+        }
+    }
+    let excluded = match &options.dedup {
+        Some(dedup) => {
+            let duplicates = dedup.check_file(file_index, &articles_vec, &path);
+            duplicates
+                .into_iter()
+                .map(|is_duplicate| is_duplicate && dedup.exclude)
+                .collect()
+        }
+        None => vec![false; articles_vec.len()],
+    };
+    for (art, excluded) in articles_vec.into_iter().zip(excluded) {
+        if excluded {
+            continue;
+        }
+        if let Some(rows) = &mut stats.article_rows {
+            rows.add(&art, &path)?;
+        }
+        match &mut stats.approx {
+            Some(approx) => {
+                approx.map_words(&art.title);
+                approx.map_words(&art.text);
+            }
+            None => WordsFrequencyMap::map_article(
+                (!options.skip_words).then_some(&mut stats.words_freq),
+                (!options.skip_lower).then_some(&mut stats.low_words_freq),
+                &art,
+            ),
+        }
+        if let Some(cooccur) = &mut stats.cooccur {
+            cooccur.map_words(&art.title);
+            cooccur.map_words(&art.text);
+        }
+        if let Some(char_stats) = &mut stats.char_stats {
+            char_stats.map_text(&art.title);
+            char_stats.map_text(&art.text);
+        }
+        if options.detect_languages {
+            let language = lang::detect_language(&art.text);
+            let language_words = stats.languages.entry(language.to_string()).or_default();
+            language_words.articles += 1;
+            WordsFrequencyMap::map_article(
+                Some(&mut language_words.words_freq),
+                Some(&mut language_words.low_words_freq),
+                &art,
+            );
+        }
+        if !options.skip_longest_article && art.text.len() > stats.long_art.size {
+            stats.long_art = LongestItem::new_longest_article(&art, &path);
+        }
+        if !options.skip_longest_title && art.title.len() > stats.long_title.size {
+            stats.long_title = LongestItem::new_longest_title(&art, &path);
+        }
+        stats.spill_if_needed()?;
+    }
+    Ok(stats)
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Sections of JSON values
+    Json,
+    /// Sections of 'word: count' lines
+    Plain,
+    /// Binary sorted table (.idx), inspected with the 'query' subcommand
+    Index,
+    /// SQLite database (.db); see the README for its tables
+    Sqlite,
+    /// One tab separated row per line (.tsv): the section, then its columns
+    Tsv,
+}
+
+impl OutputFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Json | OutputFormat::Plain => ".txt",
+            OutputFormat::Index => ".idx",
+            OutputFormat::Sqlite => ".db",
+            OutputFormat::Tsv => ".tsv",
+        }
+    }
+
+    fn default_output(self) -> &'static str {
+        match self {
+            OutputFormat::Json | OutputFormat::Plain => "stats.txt",
+            OutputFormat::Index => "stats.idx",
+            OutputFormat::Sqlite => "stats.db",
+            OutputFormat::Tsv => "stats.tsv",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Section {
+    /// As-written frequency list
+    Words,
+    /// Lowercase frequency list
+    Lower,
+    LongestArticle,
+    LongestTitle,
+    /// Word lengths & Zipf fit
+    Distribution,
+    /// Summary & error bounds of --approx
+    Approx,
+    /// Articles & frequency lists of each language (--languages)
+    Languages,
+    /// Co-occurrences (--cooccur)
+    Cooccur,
+    /// Characters, scripts, code points & bytes (--char-stats)
+    Chars,
+    /// Report & clusters of --dedup
+    Duplicates,
+}
+
+fn print_longest_item(label: &str, item: &LongestItem) {
+    println!(
+        "{}:\nTitle: {}\nPath: {}\nSize: {}",
+        label, item.title, item.path, item.size
+    );
+}
+
+fn run_query(args: cli::QueryArguments) -> Result<()> {
+    let stats_index = index::StatsIndex::open(&args.index)?;
+    let table = stats_index.table(args.lowercase);
+
+    if let Some(word) = &args.word {
+        match table.find(word)? {
+            Some(i) => println!(
+                "{}: {} (rank {} of {})",
+                word,
+                table.count(i),
+                table.rank(i)? + 1,
+                table.len()
+            ),
+            None => println!("{}: not found", word),
+        }
+    }
+    if let Some(prefix) = &args.prefix {
+        let matches = table.with_prefix(prefix)?;
+        println!(
+            "{} words starting with \"{}\" (showing at most {}):",
+            matches.len(),
+            prefix,
+            args.limit
+        );
+        for i in matches.into_iter().take(args.limit) {
+            println!("{}: {}", table.word(i)?, table.count(i));
+        }
+    }
+    if let Some(top) = args.top {
+        println!("Top {} words:", top.min(table.len()));
+        for rank in 0..top {
+            let Some(i) = table.at_rank(rank)? else {
+                break;
+            };
+            println!("{}: {}", table.word(i)?, table.count(i));
+        }
+    }
+    if let Some(rank) = args.rank {
+        match rank
+            .checked_sub(1)
+            .map(|r| table.at_rank(r))
+            .transpose()?
+            .flatten()
+        {
+            Some(i) => println!("#{}: {}: {}", rank, table.word(i)?, table.count(i)),
+            None => println!("#{}: no word has this rank", rank),
+        }
+    }
+    if args.longest {
+        print_longest_item("Longest article", stats_index.longest_article());
+        print_longest_item("Longest title", stats_index.longest_title());
+    }
+    Ok(())
+}
+
+pub fn write_stats(
+    stats: &mut StatsPackage,
+    format: OutputFormat,
+    sections: &[Section],
+    run_info: &RunInfo,
+    target: &output::OutputTarget,
+) -> Result<()> {
+    match format {
+        OutputFormat::Json => write_stats_to_file(stats, sections, target),
+        OutputFormat::Plain => write_stats_to_file_plain(stats, sections, target),
+        OutputFormat::Index => index::write_stats_to_file_index(stats, target),
+        OutputFormat::Sqlite => {
+            sqlite::write_stats_to_file_sqlite(stats, sections, run_info, target)
+        }
+        OutputFormat::Tsv => tsv::write_stats_to_file_tsv(stats, sections, target),
+    }
+}
+
+fn write_output(
+    mut stats: StatsPackage,
+    args: &cli::OutputArguments,
+    run_info: &RunInfo,
+    target: &output::OutputTarget,
+) -> Result<()> {
+    // Returned, so that a failed write (e.g. a failed rename, --no-clobber's check when moving
+    // the output into place, a closed pipe) makes the program exit with an error.
+    write_stats(
+        &mut stats,
+        args.format(),
+        &args.sections(),
+        run_info,
+        target,
+    )
+    .context("An error occured while writing output")?;
+    info!("Successfully written stats to output file.");
+    Ok(())
+}
+
+fn run_merge(args: cli::MergeArguments) -> Result<()> {
+    let output = args.output.target();
+    output.check_clobber()?;
+
+    let started_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let start_time = Instant::now();
+    let mut merged_stats = StatsPackage::new();
+    let mut input_size = 0;
+    for input in &args.inputs {
+        merged_stats.merge_with(reader::read_stats_from_file(input)?);
+        input_size += fs::metadata(input)?.len();
+    }
+    // Outputs written without the as-written list only have the lowercase one.
+    if args.output.sections().contains(&Section::Distribution) {
+        merged_stats.compute_distribution(merged_stats.words_freq.pairs.is_empty())?;
+    }
+    let run_info = RunInfo {
+        input: args.inputs.join(","),
+        input_size,
+        files_total: args.inputs.len(),
+        files_processed: args.inputs.len(),
+        started_at,
+        processing_millis: start_time.elapsed().as_millis(),
+    };
+
+    write_output(merged_stats, &args.output, &run_info, &output)?;
+    info!("Ok bye.");
+    Ok(())
+}
+
+// Processes the .json files of the archive, with one worker per file and up to `threads`
+// workers at a time.
+fn analyze_archive(
+    dataset: &str,
+    options: &AnalysisOptions,
+    threads: usize,
+    metrics: bool,
+) -> Result<(StatsPackage, RunInfo)> {
+    let mut archive = archive::open(dataset)?;
+
+    let mut workers_handles = vec![];
+    let mut workers_slice = vec![];
+    // The sketches of --approx, each one taken by the worker of one of its files at a time (there
+    // are at most as many workers as sketches); they are merged once all files are processed.
+    let mut sketches: Vec<Option<sketch::ApproxStats>> =
+        (0..sketch::SKETCHES).map(|_| None).collect();
+
+    let mut complete_stats = StatsPackage::new();
+    complete_stats.spill = options.spill.clone();
+    let mut per_article_output = options
+        .per_article
+        .as_ref()
+        .map(per_article::PerArticleOutput::create)
+        .transpose()?;
+    let no_files = archive.len();
+    let mut no_json_files = 0;
+
+    let started_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let start_time = Instant::now();
+    for index in 0..no_files {
+        let file_name = archive::entry_name(&mut archive, index)?;
+        if file_name.ends_with(".json") {
+            let mut worker_archive = archive.clone();
+            let worker_options = options.clone();
+            let file_index = no_json_files;
+            let sketch = options.approx.as_ref().map(|config| {
+                sketches[file_index % sketch::SKETCHES]
+                    .take()
+                    .unwrap_or_else(|| sketch::ApproxStats::new(config))
+            });
+            let thread_handle = thread::spawn(move || {
+                let _turn = worker_options
+                    .dedup
+                    .as_ref()
+                    .map(|dedup| dedup.turn_guard(file_index));
+                let span = debug_span!(
+                    "file",
+                    entry = %file_name,
+                    size = field::Empty,
+                    articles = field::Empty
+                );
+                let _entered = span.enter();
+                let start_time = Instant::now();
+                let data = archive::read_entry(&mut worker_archive, index)?;
+                span.record("size", data.len());
+                let file_stats = process_file_with_sketch(
+                    data,
+                    file_name.clone(),
+                    file_index,
+                    worker_options,
+                    sketch,
+                )
+                .with_context(|| format!("Failed to process {}", file_name));
+                debug!(duration = ?start_time.elapsed(), "File processed");
+                file_stats
+            });
+            workers_handles.push((file_index, thread_handle));
+            no_json_files += 1;
+        }
+        if workers_handles.len() == threads || index == no_files - 1 {
+            workers_slice.append(&mut workers_handles);
+            for (file_index, worker) in workers_slice.drain(..) {
+                // Errors are returned rather than exiting, so that 'watch' can skip the archive.
+                let mut w_s = match worker.join() {
+                    Ok(worker_stats) => {
+                        worker_stats.context("Worker thread couldn't process data about a file")?
+                    }
+                    Err(_) => bail!("A worker thread panicked while processing a file"),
+                };
+                if let Some(sketch) = w_s.approx.take() {
+                    sketches[file_index % sketch::SKETCHES] = Some(sketch);
+                }
+                // Appended as the workers are joined, i.e. in the order of the archive.
+                if let (Some(output), Some(rows)) =
+                    (&mut per_article_output, w_s.article_rows.take())
+                {
+                    output.append(rows)?;
+                }
+                complete_stats.merge_with(w_s);
+                complete_stats.spill_if_needed()?;
+            }
+            workers_handles.clear();
+            if metrics {
+                info!(
+                    "Processed {}/{} files ({:.2}% done).",
+                    index + 1,
+                    no_files,
+                    (index + 1) as f32 / no_files as f32 * 100.0
+                )
+            }
+        }
+    }
+
+    for sketch in sketches.into_iter().flatten() {
+        match &mut complete_stats.approx {
+            Some(approx) => approx.merge_with(sketch),
+            None => complete_stats.approx = Some(sketch),
+        }
+    }
+    if let Some(config) = &options.approx {
+        complete_stats.finish_approx(config);
+    }
+    complete_stats.finish_cooccur();
+    complete_stats.dedup_report = options.dedup.as_ref().map(|dedup| dedup.report());
+    complete_stats.finish_spill()?;
+    if !options.skip_distribution {
+        complete_stats.compute_distribution(options.skip_words)?;
+    }
+    if let Some(output) = per_article_output {
+        output.finish()?;
+    }
+
+    let time_passed = start_time.elapsed();
+    let file_metadata = fs::metadata(dataset)?;
+    if metrics {
+        info!(
+            "It took ~{}s {}ms to process all {} files (~{} bytes compressed size).",
+            time_passed.as_secs(),
+            time_passed.subsec_millis(),
+            archive.len(),
+            file_metadata.len()
+        );
+    }
+    let run_info = RunInfo {
+        input: dataset.to_string(),
+        input_size: file_metadata.len(),
+        files_total: no_files,
+        files_processed: no_json_files,
+        started_at,
+        processing_millis: time_passed.as_millis(),
+    };
+
+    Ok((complete_stats, run_info))
+}
+
+fn run_analyze(args: cli::AnalyzeArguments) -> Result<()> {
+    let dataset: &str = match &args.input {
+        Some(s) if s.ends_with(".zip") => s,
+        _ => "datasets/dataset.zip",
+    };
+
+    let output = args.output.target();
+    let per_article = args.per_article.map(|path| output::OutputTarget {
+        compression: compress::Compression::from_path(&path),
+        path,
+        no_clobber: args.output.no_clobber,
+    });
+    // Fail before processing the dataset rather than after.
+    output.check_clobber()?;
+    if let Some(target) = &per_article {
+        if target.is_stdout() && output.is_stdout() {
+            bail!("The output and --per-article can't both be written to the standard output");
+        }
+        target.check_clobber()?;
+    }
+
+    let mut threads = args
+        .threads
+        .map_or_else(num_cpus::get, |threads| threads as usize);
+    // One sketch per worker.
+    if args.approx {
+        threads = threads.min(sketch::SKETCHES);
+    }
+
+    let sections = args.output.sections();
+    let options = AnalysisOptions {
+        // The options whose section isn't written aren't computed.
+        detect_languages: args.languages && sections.contains(&Section::Languages),
+        strip_markup: args.strip_markup,
+        approx: if args.approx {
+            Some(sketch::ApproxConfig::from_memory_limit(
+                args.memory_limit,
+                args.top_k,
+            )?)
+        } else {
+            None
+        },
+        spill: if args.spill {
+            Some(spill::SpillConfig::new(
+                &args.spill_dir.clone().unwrap_or_else(std::env::temp_dir),
+                args.spill_threshold,
+                threads + 1,
+            )?)
+        } else {
+            None
+        },
+        cooccur: match &args.cooccur {
+            Some(path) if sections.contains(&Section::Cooccur) => Some(
+                cooccur::CooccurConfig::load(path, args.window as usize, args.cooccur_limit)?,
+            ),
+            _ => None,
+        },
+        char_stats: args.char_stats && sections.contains(&Section::Chars),
+        skip_words: !sections.contains(&Section::Words),
+        // The co-occurrences' PMI scores need the lowercase list, as does the distribution
+        // without the as-written one.
+        skip_lower: !sections.contains(&Section::Lower)
+            && !sections.contains(&Section::Cooccur)
+            && !sections.contains(&Section::Distribution),
+        skip_longest_article: !sections.contains(&Section::LongestArticle),
+        skip_longest_title: !sections.contains(&Section::LongestTitle),
+        skip_distribution: !sections.contains(&Section::Distribution),
+        // Still needed without its section to exclude the duplicates from the counts.
+        dedup: if args.dedup && (args.dedup_exclude || sections.contains(&Section::Duplicates)) {
+            Some(dedup::DedupConfig::new(
+                args.dedup_threshold,
+                args.dedup_exclude,
+            ))
+        } else {
+            None
+        },
+        per_article,
+    };
+    let (complete_stats, run_info) = analyze_archive(dataset, &options, threads, args.metrics)?;
+
+    if args.metrics {
+        info!("Now printing to output file.");
+    }
+    let start_time = Instant::now();
+    write_output(complete_stats, &args.output, &run_info, &output)?;
+
+    if args.metrics {
+        let time_passed = start_time.elapsed();
+        info!(
+            "It took ~{}s {}ms to print all stats to output file.",
+            time_passed.as_secs(),
+            time_passed.subsec_millis()
+        );
+    }
+    info!("Ok bye.");
+    Ok(())
+}
+
+// The whole program; main.rs only calls it, so that the benches can use the library.
+pub fn run() -> Result<()> {
+    // The config file provides defaults for the options, so it has to be known before the
+    // command line is parsed.
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let config = config::Config::from_args(&cli::Cli::command(), &args)?;
+    let mut command = config.apply(cli::Cli::command())?;
+    let matches = command.clone().get_matches_from(args);
+    cli::check_default_command_options(&mut command, &matches);
+    let cli = cli::Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    logging::init(
+        logging::level(cli.verbose, cli.quiet),
+        cli.log_file.as_deref(),
+    )?;
+
+    let Some(cli_command) = cli.command else {
+        return run_analyze(cli.analyze);
+    };
+    match cli_command {
+        cli::Command::Analyze(args) => run_analyze(args),
+        cli::Command::Merge(args) => run_merge(args),
+        cli::Command::Watch(args) => watch::run_watch(args),
+        cli::Command::Diff(args) => {
+            let old = reader::read_stats_from_file(&args.old)?;
+            let new = reader::read_stats_from_file(&args.new)?;
+            diff::print_stats_diff(&old, &new, args.lowercase, args.limit, args.min_count);
+            Ok(())
+        }
+        cli::Command::Query(args) => run_query(args),
+        cli::Command::Serve(args) => serve::run_serve(args),
+        cli::Command::Config(cli::ConfigCommand::Show) => config.print_effective(
+            command
+                .find_subcommand(cli::ANALYZE_COMMAND)
+                .expect("analyze subcommand is defined"),
+        ),
+        cli::Command::Completions(args) => {
+            clap_complete::generate(
+                args.shell,
+                &mut command,
+                env!("CARGO_PKG_NAME"),
+                &mut io::stdout(),
+            );
+            Ok(())
+        }
+        cli::Command::Man(args) => cli::write_man_pages(command, args.out_dir.as_deref()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::BTreeMap;
+
+    type Words = HashMap<String, u32>;
+    type RawStats = (
+        Words,
+        Words,
+        LongestItem,
+        LongestItem,
+        HashMap<String, (u32, Words, Words)>,
+    );
+
+    // Comparable form of the merged parts of a StatsPackage.
+    type Snapshot = (
+        BTreeMap<String, u32>,
+        BTreeMap<String, u32>,
+        (String, String, usize),
+        (String, String, usize),
+        BTreeMap<String, (u32, BTreeMap<String, u32>, BTreeMap<String, u32>)>,
+    );
+
+    // Few distinct words & sizes, so that the maps overlap and the longest items tie.
+    fn words() -> impl Strategy<Value = Words> {
+        prop::collection::hash_map("[a-cA-C]{1,2}", 1..1000u32, 0..8)
+    }
+
+    // Items with the same path are the same article, and are never empty (an empty article
+    // never replaces the initial item), as in real runs.
+    fn longest_item() -> impl Strategy<Value = LongestItem> {
+        ("[a-d]{0,2}", 1..4usize).prop_map(|(title, size)| LongestItem {
+            path: format!("f.json/{}", title),
+            title,
+            size,
+        })
+    }
+
+    fn raw_stats() -> impl Strategy<Value = RawStats> {
+        (
+            words(),
+            words(),
+            longest_item(),
+            longest_item(),
+            prop::collection::hash_map("[a-c]", (1..100u32, words(), words()), 0..3),
+        )
+    }
+
+    fn build((words, low_words, long_art, long_title, languages): &RawStats) -> StatsPackage {
+        let mut stats = StatsPackage::new();
+        stats.words_freq.pairs = words.clone();
+        stats.low_words_freq.pairs = low_words.clone();
+        stats.long_art = long_art.clone();
+        stats.long_title = long_title.clone();
+        for (language, (articles, words, low_words)) in languages {
+            let language_words = stats.languages.entry(language.clone()).or_default();
+            language_words.articles = *articles;
+            language_words.words_freq.pairs = words.clone();
+            language_words.low_words_freq.pairs = low_words.clone();
+        }
+        stats
+    }
+
+    fn snapshot(stats: StatsPackage) -> Snapshot {
+        let sorted = |words: WordsFrequencyMap| words.pairs.into_iter().collect();
+        let item = |item: LongestItem| (item.title, item.path, item.size);
+        (
+            sorted(stats.words_freq),
+            sorted(stats.low_words_freq),
+            item(stats.long_art),
+            item(stats.long_title),
+            stats
+                .languages
+                .into_iter()
+                .map(|(language, words)| {
+                    (
+                        language,
+                        (
+                            words.articles,
+                            sorted(words.words_freq),
+                            sorted(words.low_words_freq),
+                        ),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    fn merged(mut stats: StatsPackage, other: StatsPackage) -> StatsPackage {
+        stats.merge_with(other);
+        stats
+    }
+
+    proptest! {
+        #[test]
+        fn merge_is_commutative(a in raw_stats(), b in raw_stats()) {
+            prop_assert_eq!(
+                snapshot(merged(build(&a), build(&b))),
+                snapshot(merged(build(&b), build(&a)))
+            );
+        }
+
+        #[test]
+        fn merge_is_associative(a in raw_stats(), b in raw_stats(), c in raw_stats()) {
+            prop_assert_eq!(
+                snapshot(merged(merged(build(&a), build(&b)), build(&c))),
+                snapshot(merged(build(&a), merged(build(&b), build(&c))))
+            );
+        }
+
+        #[test]
+        fn empty_stats_are_neutral(a in raw_stats()) {
+            prop_assert_eq!(
+                snapshot(merged(build(&a), StatsPackage::new())),
+                snapshot(build(&a))
+            );
+            prop_assert_eq!(
+                snapshot(merged(StatsPackage::new(), build(&a))),
+                snapshot(build(&a))
+            );
+        }
+    }
+}
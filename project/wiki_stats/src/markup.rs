@@ -0,0 +1,531 @@
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum MarkupMode {
+    #[default]
+    None,
+    Wikitext,
+    Html,
+}
+
+// Namespaces of links that don't show up as text in a rendered article.
+const HIDDEN_LINK_NAMESPACES: &[&str] = &["file", "image", "media", "category"];
+
+pub fn strip_markup(text: &str, mode: MarkupMode) -> String {
+    match mode {
+        MarkupMode::None => text.to_string(),
+        MarkupMode::Wikitext => strip_wikitext(text),
+        MarkupMode::Html => strip_html(text),
+    }
+}
+
+fn strip_wikitext(text: &str) -> String {
+    let text = remove_between(text, "<!--", "-->");
+    let text = remove_refs(&text);
+    let text = remove_nested(&text, "{{", "}}");
+    let text = remove_nested(&text, "{|", "|}");
+    let text = replace_links(&text);
+    let text = replace_external_links(&text);
+    let text = remove_urls(&text);
+    let text = remove_tags(&text);
+    let text = strip_headings(&text);
+    let text = remove_emphasis(&text);
+    let text = remove_magic_words(&text);
+    decode_entities(&text)
+}
+
+fn strip_html(text: &str) -> String {
+    let text = remove_between(text, "<!--", "-->");
+    let text = remove_between(&text, "<script", "</script>");
+    let text = remove_between(&text, "<style", "</style>");
+    let text = remove_tags(&text);
+    decode_entities(&text)
+}
+
+fn next_char(text: &str) -> usize {
+    text.chars().next().map_or(0, char::len_utf8)
+}
+
+// Removes everything from `open` up to and including the next `close`.
+fn remove_between(text: &str, open: &str, close: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(open) {
+        out.push_str(&rest[..start]);
+        rest = match rest[start..].find(close) {
+            Some(end) => &rest[start + end + close.len()..],
+            None => "",
+        };
+    }
+    out.push_str(rest);
+    out
+}
+
+// Removes `open` ... `close` blocks, which may contain each other (e.g. templates in templates).
+fn remove_nested(text: &str, open: &str, close: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut depth = 0usize;
+    let mut rest = text;
+    while !rest.is_empty() {
+        if rest.starts_with(open) {
+            depth += 1;
+            rest = &rest[open.len()..];
+        } else if depth > 0 && rest.starts_with(close) {
+            depth -= 1;
+            rest = &rest[close.len()..];
+        } else {
+            let len = next_char(rest);
+            if depth == 0 {
+                out.push_str(&rest[..len]);
+            }
+            rest = &rest[len..];
+        }
+    }
+    out
+}
+
+fn line_end(text: &str) -> usize {
+    text.find('\n').unwrap_or(text.len())
+}
+
+// Removes both <ref>...</ref> footnotes and self-closing <ref name="..." /> references.
+// Dumps have unclosed refs & truncated tags: those are only removed up to the end of their line,
+// rather than swallowing the rest of the article. A footnote must close before a blank line.
+fn remove_refs(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("<ref") {
+        out.push_str(&rest[..start]);
+        let tag = &rest[start..];
+        let is_ref_tag = matches!(tag[4..].chars().next(), Some(' ' | '>' | '/'));
+        if !is_ref_tag {
+            out.push_str("<ref");
+            rest = &tag[4..];
+            continue;
+        }
+        rest = match tag.find('>') {
+            Some(tag_end) if tag_end > line_end(tag) => &tag[line_end(tag)..],
+            Some(tag_end) if tag[..tag_end].ends_with('/') => &tag[tag_end + 1..],
+            Some(_) => {
+                let paragraph_end = tag.find("\n\n").unwrap_or(tag.len());
+                match tag[..paragraph_end].find("</ref>") {
+                    Some(end) => &tag[end + "</ref>".len()..],
+                    None => &tag[line_end(tag)..],
+                }
+            }
+            None => &tag[line_end(tag)..],
+        };
+    }
+    out.push_str(rest);
+    out
+}
+
+fn matching_link_end(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut pos = 0;
+    while pos < text.len() {
+        let rest = &text[pos..];
+        if rest.starts_with("[[") {
+            depth += 1;
+            pos += 2;
+        } else if rest.starts_with("]]") {
+            if depth == 0 {
+                return Some(pos);
+            }
+            depth -= 1;
+            pos += 2;
+        } else {
+            pos += next_char(rest);
+        }
+    }
+    None
+}
+
+fn link_text(inner: &str) -> String {
+    let target = inner.split('|').next().unwrap_or_default().trim();
+    if let Some((namespace, _)) = target.split_once(':') {
+        if HIDDEN_LINK_NAMESPACES.contains(&namespace.trim().to_lowercase().as_str()) {
+            return String::new();
+        }
+    }
+    replace_links(inner.rsplit('|').next().unwrap_or_default())
+}
+
+// [[Target|display text]] -> display text, [[Target]] -> Target.
+fn replace_links(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        out.push_str(&rest[..start]);
+        let inner = &rest[start + 2..];
+        match matching_link_end(inner) {
+            Some(end) => {
+                out.push_str(&link_text(&inner[..end]));
+                rest = &inner[end + 2..];
+            }
+            None => {
+                rest = inner;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// [http://example.com display text] -> display text, [http://example.com] -> nothing.
+fn replace_external_links(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('[') {
+        out.push_str(&rest[..start]);
+        let inner = &rest[start + 1..];
+        let is_external = inner.starts_with("http://")
+            || inner.starts_with("https://")
+            || inner.starts_with("//");
+        match inner.find(']') {
+            Some(end) if is_external => {
+                if let Some((_, display)) = inner[..end].split_once(' ') {
+                    out.push_str(display);
+                }
+                rest = &inner[end + 1..];
+            }
+            _ => {
+                out.push('[');
+                rest = inner;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn remove_urls(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("http") {
+        out.push_str(&rest[..start]);
+        let url = &rest[start..];
+        if url.starts_with("http://") || url.starts_with("https://") {
+            let end = url.find(char::is_whitespace).unwrap_or(url.len());
+            rest = &url[end..];
+        } else {
+            out.push_str("http");
+            rest = &url[4..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// Drops the tags themselves but keeps their content, e.g. <b>text</b> -> text.
+fn remove_tags(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        let tag = &rest[start + 1..];
+        let is_tag = tag
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '/' || c == '!');
+        match tag.find('>') {
+            Some(end) if is_tag => {
+                out.push(' ');
+                rest = &tag[end + 1..];
+            }
+            _ => {
+                out.push('<');
+                rest = tag;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// ==Heading== -> Heading
+fn strip_headings(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let trimmed = line.trim();
+            if trimmed.len() > 1 && trimmed.starts_with('=') && trimmed.ends_with('=') {
+                trimmed.trim_matches('=').trim()
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+// ''italic'' & '''bold''' quotes would otherwise stick to words, as ' is part of a word.
+fn remove_emphasis(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("''") {
+        out.push_str(&rest[..start]);
+        rest = rest[start..].trim_start_matches('\'');
+    }
+    out.push_str(rest);
+    out
+}
+
+// __NOTOC__, __TOC__ and the like.
+fn remove_magic_words(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("__") {
+        out.push_str(&rest[..start]);
+        let word = &rest[start + 2..];
+        match word.find("__") {
+            Some(end) if end > 0 && word[..end].chars().all(|c| c.is_ascii_uppercase()) => {
+                rest = &word[end + 2..];
+            }
+            _ => {
+                out.push_str("__");
+                rest = word;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// Names of the Latin-1 characters, U+00A0 to U+00FF.
+const LATIN_1_ENTITIES: [&str; 96] = [
+    "nbsp", "iexcl", "cent", "pound", "curren", "yen", "brvbar", "sect", "uml", "copy", "ordf",
+    "laquo", "not", "shy", "reg", "macr", "deg", "plusmn", "sup2", "sup3", "acute", "micro",
+    "para", "middot", "cedil", "sup1", "ordm", "raquo", "frac14", "frac12", "frac34", "iquest",
+    "Agrave", "Aacute", "Acirc", "Atilde", "Auml", "Aring", "AElig", "Ccedil", "Egrave", "Eacute",
+    "Ecirc", "Euml", "Igrave", "Iacute", "Icirc", "Iuml", "ETH", "Ntilde", "Ograve", "Oacute",
+    "Ocirc", "Otilde", "Ouml", "times", "Oslash", "Ugrave", "Uacute", "Ucirc", "Uuml", "Yacute",
+    "THORN", "szlig", "agrave", "aacute", "acirc", "atilde", "auml", "aring", "aelig", "ccedil",
+    "egrave", "eacute", "ecirc", "euml", "igrave", "iacute", "icirc", "iuml", "eth", "ntilde",
+    "ograve", "oacute", "ocirc", "otilde", "ouml", "divide", "oslash", "ugrave", "uacute", "ucirc",
+    "uuml", "yacute", "thorn", "yuml",
+];
+
+// Other named entities: punctuation, spaces & the Latin letters of HTML 4. Spaces decode to
+// a plain space and invisible characters to nothing, so that they don't split or glue words.
+const OTHER_ENTITIES: &[(&str, &str)] = &[
+    ("nbsp", " "),
+    ("shy", ""),
+    ("amp", "&"),
+    ("lt", "<"),
+    ("gt", ">"),
+    ("quot", "\""),
+    ("apos", "'"),
+    ("ndash", "–"),
+    ("mdash", "—"),
+    ("lsquo", "‘"),
+    ("rsquo", "’"),
+    ("sbquo", "‚"),
+    ("ldquo", "“"),
+    ("rdquo", "”"),
+    ("bdquo", "„"),
+    ("hellip", "…"),
+    ("bull", "•"),
+    ("prime", "′"),
+    ("Prime", "″"),
+    ("lsaquo", "‹"),
+    ("rsaquo", "›"),
+    ("oline", "‾"),
+    ("frasl", "⁄"),
+    ("dagger", "†"),
+    ("Dagger", "‡"),
+    ("permil", "‰"),
+    ("euro", "€"),
+    ("trade", "™"),
+    ("minus", "−"),
+    ("larr", "←"),
+    ("rarr", "→"),
+    ("uarr", "↑"),
+    ("darr", "↓"),
+    ("harr", "↔"),
+    ("ensp", " "),
+    ("emsp", " "),
+    ("thinsp", " "),
+    ("zwnj", ""),
+    ("zwj", ""),
+    ("lrm", ""),
+    ("rlm", ""),
+    ("OElig", "Œ"),
+    ("oelig", "œ"),
+    ("Scaron", "Š"),
+    ("scaron", "š"),
+    ("Yuml", "Ÿ"),
+    ("fnof", "ƒ"),
+    ("circ", "ˆ"),
+    ("tilde", "˜"),
+];
+
+// Names longer than this aren't entities (the longest HTML one has 31 characters).
+const MAX_ENTITY_LENGTH: usize = 32;
+
+// Decodes the entity between '&' & ';' into `out`. Unknown names & invalid code points are
+// markup all the same, so they're dropped rather than counted as words; returns false if it
+// isn't an entity at all (not only letters & digits).
+fn decode_entity(entity: &str, out: &mut String) -> bool {
+    if let Some(number) = entity.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => number.parse(),
+        };
+        if let Some(c) = code.ok().and_then(char::from_u32) {
+            out.push(c);
+        }
+        return !number.is_empty() && number.chars().all(|c| c.is_ascii_alphanumeric());
+    }
+    if entity.is_empty() || !entity.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return false;
+    }
+    if let Some(&(_, decoded)) = OTHER_ENTITIES.iter().find(|(name, _)| *name == entity) {
+        out.push_str(decoded);
+    } else if let Some(i) = LATIN_1_ENTITIES.iter().position(|&name| name == entity) {
+        out.extend(char::from_u32(0xA0 + i as u32));
+    }
+    true
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        let entity = &rest[start + 1..];
+        match entity
+            .find(';')
+            .filter(|&end| end <= MAX_ENTITY_LENGTH && decode_entity(&entity[..end], &mut out))
+        {
+            Some(end) => rest = &entity[end + 1..],
+            None => {
+                out.push('&');
+                rest = entity;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<&str> {
+        crate::split_words(text).collect()
+    }
+
+    #[test]
+    fn refs() {
+        assert_eq!(
+            remove_refs(r#"Fact.<ref name="a">Source, p. 3</ref> More.<ref name="a" /> End."#),
+            "Fact. More. End."
+        );
+        // Multi-line footnotes are removed whole.
+        assert_eq!(remove_refs("A<ref>{{cite\n|title=T}}</ref> b"), "A b");
+        // Not ref tags.
+        assert_eq!(
+            remove_refs("<references /> <refx>"),
+            "<references /> <refx>"
+        );
+    }
+
+    #[test]
+    fn unclosed_refs_keep_the_rest_of_the_article() {
+        assert_eq!(
+            remove_refs("One<ref name=x>unclosed source\nTwo\n\nThree"),
+            "One\nTwo\n\nThree"
+        );
+        // The closing tag of a later paragraph's footnote isn't this one's.
+        assert_eq!(
+            remove_refs("One<ref>unclosed\n\nTwo<ref>note</ref> three"),
+            "One\n\nTwo three"
+        );
+        // Truncated tags, without their '>'.
+        assert_eq!(
+            remove_refs("One<ref name=\"x\nTwo<ref>note</ref>"),
+            "One\nTwo"
+        );
+        assert_eq!(remove_refs("End<ref "), "End");
+    }
+
+    #[test]
+    fn templates() {
+        assert_eq!(
+            remove_nested(
+                "Before {{Infobox|name={{lang|fr|Paris}}}} after",
+                "{{",
+                "}}"
+            ),
+            "Before  after"
+        );
+        // An unclosed template hides the rest, as it does when rendered.
+        assert_eq!(remove_nested("a {{b c", "{{", "}}"), "a ");
+        // Stray closing braces are text.
+        assert_eq!(remove_nested("a }} b", "{{", "}}"), "a }} b");
+    }
+
+    #[test]
+    fn tables() {
+        assert_eq!(
+            strip_wikitext("Intro\n{| class=\"wikitable\"\n|-\n| cell {{tpl}}\n|}\nOutro"),
+            "Intro\n\nOutro"
+        );
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(
+            replace_links("[[Paris]] and [[Lyon|the city]]"),
+            "Paris and the city"
+        );
+        // Links in the caption of a file link are hidden with it.
+        assert_eq!(
+            replace_links("A[[File:x.jpg|thumb|A [[nested]] caption]]B"),
+            "AB"
+        );
+        assert_eq!(
+            replace_links("[[Category:Cities]][[Paris|capital of [[France]]]]"),
+            "capital of France"
+        );
+        // Unclosed links keep their text.
+        assert_eq!(replace_links("a [[b c"), "a b c");
+        assert_eq!(
+            replace_external_links("[https://example.com Example site] [http://x.org]"),
+            "Example site "
+        );
+    }
+
+    #[test]
+    fn other_markup() {
+        assert_eq!(
+            words(&strip_wikitext(
+                "==History==\n'''Bold''' ''italic'' <b>tag</b> __NOTOC__ https://x.org &amp; &#233;t&eacute;"
+            )),
+            vec!["History", "Bold", "italic", "tag", "été"]
+        );
+        assert_eq!(strip_wikitext("a <!-- hidden --> b"), "a  b");
+    }
+
+    #[test]
+    fn entities() {
+        assert_eq!(
+            decode_entities("Caf&eacute; na&iuml;ve &copy; &hellip; &THORN;&yuml;"),
+            "Café naïve © … Þÿ"
+        );
+        assert_eq!(
+            decode_entities("x&nbsp;y co&shy;op &#x41;&#66;&#xZZ;&#1114112;"),
+            "x y coop AB"
+        );
+        // Unknown entities are dropped, other ampersands are text.
+        assert_eq!(
+            words(&decode_entities("a &unknown; b &amp c AT&T R&D")),
+            vec!["a", "b", "amp", "c", "AT", "T", "R", "D"]
+        );
+        assert_eq!(
+            strip_html("<p>Hello <script>x()</script><style>p{}</style>world</p>"),
+            " Hello world "
+        );
+    }
+}
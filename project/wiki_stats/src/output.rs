@@ -0,0 +1,210 @@
+use crate::compress::{Compression, Encoder};
+use anyhow::{bail, Context, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+// Output path standing for the standard output.
+pub const STDOUT: &str = "-";
+
+#[derive(Clone)]
+pub struct OutputTarget {
+    pub path: String,
+    pub no_clobber: bool,
+    pub compression: Option<Compression>,
+}
+
+impl OutputTarget {
+    pub fn is_stdout(&self) -> bool {
+        self.path == STDOUT
+    }
+
+    pub fn check_clobber(&self) -> Result<()> {
+        if self.no_clobber && !self.is_stdout() && Path::new(&self.path).exists() {
+            bail!(
+                "Output file {} already exists (--no-clobber was given)",
+                self.path
+            );
+        }
+        Ok(())
+    }
+}
+
+enum Sink {
+    File(File),
+    Stdout(io::Stdout),
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::File(file) => file.write(buf),
+            Sink::Stdout(stdout) => stdout.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::File(file) => file.flush(),
+            Sink::Stdout(stdout) => stdout.flush(),
+        }
+    }
+}
+
+// Output written to a temp file next to the target, which only replaces the target once fully
+// written & synced to disk. A reader never sees a partial file, and a failed write leaves the
+// previous file untouched. The temp file is removed if the output is dropped before `finish`.
+// The standard output is written directly.
+pub struct OutputFile {
+    encoder: Option<Encoder<Sink>>,
+    temp_path: Option<PathBuf>,
+    scratch_path: Option<PathBuf>,
+    path: PathBuf,
+    no_clobber: bool,
+    finished: bool,
+}
+
+impl OutputFile {
+    pub fn create(target: &OutputTarget) -> Result<Self> {
+        let path = PathBuf::from(&target.path);
+        if target.is_stdout() {
+            return Ok(Self {
+                encoder: Some(Encoder::new(
+                    Sink::Stdout(io::stdout()),
+                    target.compression,
+                )?),
+                temp_path: None,
+                scratch_path: None,
+                path,
+                no_clobber: false,
+                finished: false,
+            });
+        }
+        target.check_clobber()?;
+        let file_name = path
+            .file_name()
+            .with_context(|| format!("Invalid output file name: {}", target.path))?
+            .to_string_lossy();
+        let temp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp_path)
+            .context("Failed to create output file")?;
+        Ok(Self {
+            encoder: Some(Encoder::new(Sink::File(file), target.compression)?),
+            temp_path: Some(temp_path),
+            scratch_path: None,
+            path,
+            no_clobber: target.no_clobber,
+            finished: false,
+        })
+    }
+
+    // For writers which open & write the file themselves (SQLite). When compressing or writing
+    // to the standard output, they get a scratch file instead, streamed by `finish`.
+    pub fn database_path(&mut self) -> PathBuf {
+        match (&self.encoder, &self.temp_path) {
+            (Some(Encoder::None(Sink::File(_))), Some(temp_path)) => temp_path.clone(),
+            (_, temp_path) => {
+                let scratch_path = match temp_path {
+                    Some(temp_path) => temp_path.with_extension("scratch"),
+                    None => std::env::temp_dir()
+                        .join(format!("wiki_stats-{}.scratch", std::process::id())),
+                };
+                self.scratch_path = Some(scratch_path.clone());
+                scratch_path
+            }
+        }
+    }
+
+    fn encoder(&mut self) -> &mut Encoder<Sink> {
+        self.encoder.as_mut().expect("output file already finished")
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(scratch_path) = self.scratch_path.take() {
+            let copied = File::open(&scratch_path)
+                .and_then(|mut scratch| io::copy(&mut scratch, self.encoder()));
+            fs::remove_file(&scratch_path)?;
+            copied?;
+        }
+        let mut file = match self
+            .encoder
+            .take()
+            .expect("output file already finished")
+            .finish()?
+        {
+            Sink::File(file) => file,
+            Sink::Stdout(mut stdout) => {
+                stdout.flush()?;
+                self.finished = true;
+                return Ok(());
+            }
+        };
+        let Some(temp_path) = &self.temp_path else {
+            unreachable!("output files always have a temp file");
+        };
+        file.flush()?;
+        file.sync_all()?;
+        if self.no_clobber {
+            // Unlike rename, linking fails if the target was created in the meantime.
+            fs::hard_link(temp_path, &self.path).map_err(|e| match e.kind() {
+                ErrorKind::AlreadyExists => anyhow::anyhow!(
+                    "Output file {} already exists (--no-clobber was given)",
+                    self.path.display()
+                ),
+                _ => e.into(),
+            })?;
+            fs::remove_file(temp_path)?;
+        } else {
+            fs::rename(temp_path, &self.path)?;
+        }
+        self.finished = true;
+        sync_parent_dir(&self.path)
+    }
+}
+
+// Moves a complete file into place, like `OutputFile::finish`.
+pub fn rename(from: &Path, to: &Path) -> Result<()> {
+    fs::rename(from, to)?;
+    sync_parent_dir(to)
+}
+
+// Makes the rename itself durable.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder().flush()
+    }
+}
+
+impl Drop for OutputFile {
+    fn drop(&mut self) {
+        if let (false, Some(temp_path)) = (self.finished, &self.temp_path) {
+            let _ = fs::remove_file(temp_path);
+        }
+        if let Some(scratch_path) = &self.scratch_path {
+            let _ = fs::remove_file(scratch_path);
+        }
+    }
+}
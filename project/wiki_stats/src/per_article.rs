@@ -0,0 +1,76 @@
+use crate::output::{OutputFile, OutputTarget};
+use crate::{split_words, Article};
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+
+const HEADER: &[u8] = b"path,title,bytes,characters,tokens,unique_tokens,average_word_length\n";
+
+// The rows of the articles of one file, written by its worker to an anonymous temporary file
+// as the articles are processed, so that they are never all kept in memory.
+pub struct ArticleRows {
+    writer: csv::Writer<BufWriter<File>>,
+}
+
+impl ArticleRows {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            writer: csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(BufWriter::new(tempfile::tempfile()?)),
+        })
+    }
+
+    // Lengths & tokens of the text, after --strip-markup; word lengths are in characters.
+    pub fn add(&mut self, art: &Article, path: &str) -> Result<()> {
+        let mut tokens = 0;
+        let mut characters = 0;
+        let mut unique_tokens = HashSet::new();
+        for word in split_words(&art.text) {
+            tokens += 1;
+            characters += word.chars().count();
+            unique_tokens.insert(word);
+        }
+        let average_word_length = if tokens == 0 {
+            0.0
+        } else {
+            characters as f64 / tokens as f64
+        };
+        self.writer.serialize((
+            format!("{}/{}", path, art.id),
+            &art.title,
+            art.text.len(),
+            art.text.chars().count(),
+            tokens,
+            unique_tokens.len(),
+            format!("{:.2}", average_word_length),
+        ))?;
+        Ok(())
+    }
+}
+
+// The --per-article CSV file, to which the rows of each file are appended in the order of the
+// archive, whatever the number of threads.
+pub struct PerArticleOutput {
+    file: OutputFile,
+}
+
+impl PerArticleOutput {
+    pub fn create(target: &OutputTarget) -> Result<Self> {
+        let mut file = OutputFile::create(target)?;
+        file.write_all(HEADER)?;
+        Ok(Self { file })
+    }
+
+    pub fn append(&mut self, rows: ArticleRows) -> Result<()> {
+        let mut rows = rows.writer.into_inner()?.into_inner()?;
+        rows.seek(SeekFrom::Start(0))?;
+        io::copy(&mut rows, &mut self.file)?;
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        self.file.finish()
+    }
+}
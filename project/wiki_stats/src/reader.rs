@@ -0,0 +1,159 @@
+use crate::compress;
+use crate::index::StatsIndex;
+use crate::sqlite;
+use crate::tsv;
+use crate::{LanguageArticles, LongestItem, StatsPackage, WordFreq, WordsFrequencyMap};
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+
+// Loads stats previously written by any of the writers, detecting the format (and compression)
+// from the content.
+pub fn read_stats_from_file(stats_file_path: &str) -> Result<StatsPackage> {
+    let data = compress::read_file(stats_file_path)
+        .with_context(|| format!("Failed to read stats file {}", stats_file_path))?;
+    if data.starts_with(b"WSTATIDX") {
+        return StatsIndex::from_bytes(data)?.into_stats();
+    }
+    if data.starts_with(b"SQLite format 3\0") {
+        if !compress::is_compressed(stats_file_path)? {
+            return sqlite::read_stats_from_file_sqlite(stats_file_path);
+        }
+        // SQLite can only open files: a compressed database is decompressed to a temp file.
+        let mut database = tempfile::NamedTempFile::new()?;
+        database.write_all(&data)?;
+        database.flush()?;
+        let database_path = database.path().to_string_lossy().to_string();
+        return sqlite::read_stats_from_file_sqlite(&database_path);
+    }
+    let text = String::from_utf8(data).context("Stats file is not valid UTF-8")?;
+    if tsv::is_tsv(&text) {
+        return tsv::read_stats_tsv(&text);
+    }
+    // Sections may have been left out or reordered (--sections), so the first one can be any of
+    // them. JSON values follow their header directly, plain text sections start with a blank line.
+    let sections = split_sections(text.trim_start_matches('\n'));
+    match sections.first() {
+        Some((_, body)) if body.starts_with('\n') => read_sections(
+            sections,
+            read_words_plain,
+            read_longest_item_plain,
+            read_languages_plain,
+        ),
+        Some((_, body)) if body.starts_with(['[', '{']) => read_sections(
+            sections,
+            read_words_json,
+            read_longest_item_json,
+            read_languages_json,
+        ),
+        _ => bail!("{} is not a wiki_stats output file", stats_file_path),
+    }
+}
+
+// Both text formats start every section with a "\t<name>" line, and no other line starts
+// with a tab: words never contain one and JSON is indented with spaces.
+fn split_sections(text: &str) -> Vec<(&str, &str)> {
+    let mut sections = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.strip_prefix('\t') {
+        let (name, body) = start.split_once('\n').unwrap_or((start, ""));
+        let end = body.find("\n\t").map_or(body.len(), |end| end + 1);
+        sections.push((name, &body[..end]));
+        rest = &body[end..];
+    }
+    sections
+}
+
+fn read_sections(
+    sections: Vec<(&str, &str)>,
+    read_words: fn(&str) -> Result<WordsFrequencyMap>,
+    read_longest_item: fn(&str) -> Result<LongestItem>,
+    read_languages: fn(&str) -> Result<Vec<LanguageArticles>>,
+) -> Result<StatsPackage> {
+    let mut stats = StatsPackage::new();
+    for (name, body) in sections {
+        match name {
+            "Words frequency (as written)" => stats.words_freq = read_words(body)?,
+            "Words frequency (lowercase)" => stats.low_words_freq = read_words(body)?,
+            "Longest article" => stats.long_art = read_longest_item(body)?,
+            "Longest title" => stats.long_title = read_longest_item(body)?,
+            "Languages" => {
+                for language in read_languages(body)? {
+                    stats
+                        .languages
+                        .entry(language.language)
+                        .or_default()
+                        .articles = language.articles;
+                }
+            }
+            _ => {
+                if let Some(language) = name
+                    .strip_prefix("Words frequency (as written, ")
+                    .and_then(|rest| rest.strip_suffix(')'))
+                {
+                    let language_words = stats.languages.entry(language.to_string()).or_default();
+                    language_words.words_freq = read_words(body)?;
+                } else if let Some(language) = name
+                    .strip_prefix("Words frequency (lowercase, ")
+                    .and_then(|rest| rest.strip_suffix(')'))
+                {
+                    let language_words = stats.languages.entry(language.to_string()).or_default();
+                    language_words.low_words_freq = read_words(body)?;
+                }
+            }
+        }
+    }
+    Ok(stats)
+}
+
+fn read_words_json(text: &str) -> Result<WordsFrequencyMap> {
+    let pairs_vec: Vec<WordFreq> = serde_json::from_str(text)?;
+    Ok(WordsFrequencyMap {
+        pairs: pairs_vec
+            .into_iter()
+            .map(|pair| (pair.word, pair.appearances))
+            .collect(),
+    })
+}
+
+fn read_longest_item_json(text: &str) -> Result<LongestItem> {
+    Ok(serde_json::from_str(text)?)
+}
+
+fn read_languages_json(text: &str) -> Result<Vec<LanguageArticles>> {
+    Ok(serde_json::from_str(text)?)
+}
+
+fn read_words_plain(text: &str) -> Result<WordsFrequencyMap> {
+    let mut words = WordsFrequencyMap::new();
+    for line in text.lines().filter(|line| !line.is_empty()) {
+        let (word, count) = line
+            .rsplit_once(": ")
+            .with_context(|| format!("Malformed line in stats file: {:?}", line))?;
+        words.pairs.insert(word.to_string(), count.parse()?);
+    }
+    Ok(words)
+}
+
+fn read_longest_item_plain(text: &str) -> Result<LongestItem> {
+    let mut item = LongestItem::new();
+    for line in text.lines().filter(|line| !line.is_empty()) {
+        if let Some(title) = line.strip_prefix("Title: ") {
+            item.title = title.to_string();
+        } else if let Some(path) = line.strip_prefix("Path: ") {
+            item.path = path.to_string();
+        } else if let Some(size) = line.strip_prefix("Size: ") {
+            item.size = size.parse()?;
+        } else {
+            bail!("Malformed line in stats file: {:?}", line);
+        }
+    }
+    Ok(item)
+}
+
+fn read_languages_plain(text: &str) -> Result<Vec<LanguageArticles>> {
+    Ok(read_words_plain(text)?
+        .pairs
+        .into_iter()
+        .map(|(language, articles)| LanguageArticles { language, articles })
+        .collect())
+}
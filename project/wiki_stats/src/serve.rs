@@ -0,0 +1,172 @@
+use crate::cli::ServeArguments;
+use crate::{reader, LongestItem, WordsFrequencyMap};
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{debug, info, warn};
+
+const DEFAULT_TOP: usize = 10;
+
+// The stats loaded once, with the frequency lists also sorted by number of appearances (most
+// frequent first, ties by word) for /top.
+struct ServedStats {
+    words: HashMap<String, u32>,
+    low_words: HashMap<String, u32>,
+    top_words: Vec<(String, u32)>,
+    top_low_words: Vec<(String, u32)>,
+    long_art: LongestItem,
+    long_title: LongestItem,
+}
+
+fn by_appearances(words: &WordsFrequencyMap) -> Vec<(String, u32)> {
+    let mut pairs: Vec<(String, u32)> = words
+        .pairs
+        .iter()
+        .map(|(word, &count)| (word.clone(), count))
+        .collect();
+    pairs.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    pairs
+}
+
+impl ServedStats {
+    fn load(path: &str) -> Result<Self> {
+        let stats = reader::read_stats_from_file(path)?;
+        Ok(Self {
+            top_words: by_appearances(&stats.words_freq),
+            top_low_words: by_appearances(&stats.low_words_freq),
+            words: stats.words_freq.pairs,
+            low_words: stats.low_words_freq.pairs,
+            long_art: stats.long_art,
+            long_title: stats.long_title,
+        })
+    }
+
+    fn word(&self, word: &str) -> (u16, Value) {
+        let appearances = self.words.get(word);
+        // The lowercase list is built with ASCII lowercasing.
+        let lowercase_appearances = self.low_words.get(&word.to_ascii_lowercase());
+        if appearances.is_none() && lowercase_appearances.is_none() {
+            return error(404, &format!("Word not found: {}", word));
+        }
+        (
+            200,
+            json!({
+                "word": word,
+                "appearances": appearances.copied().unwrap_or(0),
+                "lowercase_appearances": lowercase_appearances.copied().unwrap_or(0),
+            }),
+        )
+    }
+
+    fn top(&self, query: &HashMap<String, String>) -> (u16, Value) {
+        let n = match query.get("n").map(|n| n.parse::<usize>()) {
+            None => DEFAULT_TOP,
+            Some(Ok(n)) => n,
+            Some(Err(_)) => return error(400, "n must be a non-negative integer"),
+        };
+        let top = match query.get("lowercase").map(String::as_str) {
+            None | Some("false") | Some("0") => &self.top_words,
+            Some("true") | Some("1") => &self.top_low_words,
+            Some(_) => return error(400, "lowercase must be true or false"),
+        };
+        let words: Vec<Value> = top
+            .iter()
+            .take(n)
+            .map(|(word, count)| json!({ "word": word, "appearances": count }))
+            .collect();
+        (200, Value::Array(words))
+    }
+
+    fn longest(&self) -> (u16, Value) {
+        (
+            200,
+            json!({ "article": &self.long_art, "title": &self.long_title }),
+        )
+    }
+
+    fn respond(&self, method: &Method, url: &str) -> (u16, Value) {
+        if *method != Method::Get {
+            return error(405, "Only GET is supported");
+        }
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let (Some(path), Some(query)) = (percent_decode(path), parse_query(query)) else {
+            return error(400, "Malformed URL");
+        };
+        match path.as_str() {
+            "/top" => self.top(&query),
+            "/longest" => self.longest(),
+            _ => match path.strip_prefix("/word/") {
+                Some(word) if !word.is_empty() => self.word(word),
+                _ => error(404, &format!("Unknown endpoint: {}", path)),
+            },
+        }
+    }
+}
+
+fn error(status: u16, message: &str) -> (u16, Value) {
+    (status, json!({ "error": message }))
+}
+
+// Words may be any UTF-8 text, percent-encoded in the URL.
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = s.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn parse_query(query: &str) -> Option<HashMap<String, String>> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Some((
+                percent_decode(&key.replace('+', " "))?,
+                percent_decode(&value.replace('+', " "))?,
+            ))
+        })
+        .collect()
+}
+
+fn handle(stats: &ServedStats, request: Request) {
+    let (status, body) = stats.respond(request.method(), request.url());
+    debug!(method = %request.method(), url = request.url(), status, "Request");
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "application/json").expect("valid header"));
+    if let Err(e) = request.respond(response) {
+        warn!("Failed to send a response: {}", e);
+    }
+}
+
+pub fn run_serve(args: ServeArguments) -> Result<()> {
+    let stats = ServedStats::load(&args.stats)?;
+    let server = Server::http((args.bind, args.port)).map_err(|e| anyhow!(e))?;
+    // The actual address, when the port was chosen by the system (--port 0).
+    let address = server
+        .server_addr()
+        .to_ip()
+        .map_or_else(|| format!("{}:{}", args.bind, args.port), |a| a.to_string());
+    info!(
+        "Serving {} ({} words) on http://{}",
+        args.stats,
+        stats.words.len(),
+        address
+    );
+    for request in server.incoming_requests() {
+        handle(&stats, request);
+    }
+    Ok(())
+}
@@ -0,0 +1,584 @@
+use crate::{split_words, WordsFrequencyMap};
+use anyhow::{bail, Result};
+use serde_derive::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+const COUNT_MIN_DEPTH: usize = 4;
+const HYPERLOGLOG_PRECISION: u32 = 14;
+// Space-Saving monitors this many more words than reported, which keeps the top ones accurate.
+const SPACE_SAVING_FACTOR: usize = 10;
+// Rough heap cost of one monitored word (both copies of the word, counters & map overhead).
+const SPACE_SAVING_ENTRY_SIZE: usize = 160;
+const MIN_COUNT_MIN_WIDTH: usize = 1024;
+// Number of ApproxStats the files are counted into, whatever --threads: the n-th .json file goes
+// into sketch n % SKETCHES, and the sketches are merged in order at the end, so that the output
+// doesn't depend on the number of threads. Also the most threads --approx uses, each worker
+// counting into its own sketch.
+pub const SKETCHES: usize = 8;
+
+fn hash_word(word: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    word.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub fn parse_memory_size(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    let (digits, multiplier) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&s[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|size| size.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid memory size '{}' (examples: 512M, 2G)", s))
+}
+
+// Sizes of the structures of one ApproxStats. All workers must use the same config,
+// otherwise their sketches can't be merged.
+#[derive(Clone)]
+pub struct ApproxConfig {
+    top_k: usize,
+    count_min_width: usize,
+    space_saving_capacity: usize,
+}
+
+impl ApproxConfig {
+    // The limit is shared by the SKETCHES ApproxStats, of two maps each.
+    pub fn from_memory_limit(memory_limit: usize, top_k: usize) -> Result<Self> {
+        let space_saving_capacity = top_k.max(1) * SPACE_SAVING_FACTOR;
+        let per_map = memory_limit / SKETCHES / 2;
+        let fixed = (1 << HYPERLOGLOG_PRECISION) + space_saving_capacity * SPACE_SAVING_ENTRY_SIZE;
+        let count_min_width = per_map.saturating_sub(fixed) / (COUNT_MIN_DEPTH * 4);
+        if count_min_width < MIN_COUNT_MIN_WIDTH {
+            bail!(
+                "Memory limit of {} bytes is too low for {} sketches and top {} words",
+                memory_limit,
+                SKETCHES,
+                top_k
+            );
+        }
+        Ok(Self {
+            top_k,
+            count_min_width,
+            space_saving_capacity,
+        })
+    }
+}
+
+pub struct CountMinSketch {
+    width: usize,
+    counters: Vec<u32>,
+}
+
+impl CountMinSketch {
+    pub fn new(width: usize) -> Self {
+        Self {
+            width,
+            counters: vec![0; width * COUNT_MIN_DEPTH],
+        }
+    }
+
+    // Double hashing: row i uses h1 + i * h2, which is as good as independent hash functions.
+    fn cell(&self, hash: u64, row: usize) -> usize {
+        let (h1, h2) = (hash as u32 as usize, (hash >> 32) as usize | 1);
+        row * self.width + h1.wrapping_add(row.wrapping_mul(h2)) % self.width
+    }
+
+    pub fn add(&mut self, hash: u64) {
+        for row in 0..COUNT_MIN_DEPTH {
+            let cell = self.cell(hash, row);
+            self.counters[cell] = self.counters[cell].saturating_add(1);
+        }
+    }
+
+    pub fn estimate(&self, hash: u64) -> u64 {
+        (0..COUNT_MIN_DEPTH)
+            .map(|row| self.counters[self.cell(hash, row)] as u64)
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn merge_with(&mut self, other: Self) {
+        for (counter, other_counter) in self.counters.iter_mut().zip(other.counters) {
+            *counter = counter.saturating_add(other_counter);
+        }
+    }
+
+    // Estimates exceed the real count by at most e / width * total words,
+    // with probability 1 - e^(-depth).
+    pub fn max_overestimate(&self, total: u64) -> u64 {
+        (std::f64::consts::E / self.width as f64 * total as f64).ceil() as u64
+    }
+
+    pub fn confidence() -> f64 {
+        1.0 - (-(COUNT_MIN_DEPTH as f64)).exp()
+    }
+}
+
+struct Counter {
+    word: String,
+    count: u64,
+    error: u64,
+}
+
+// Space-Saving heavy hitters: the monitored words are kept in a min-heap by count, so that
+// the least frequent one can be replaced in O(log capacity).
+pub struct SpaceSaving {
+    capacity: usize,
+    heap: Vec<Counter>,
+    positions: HashMap<String, usize>,
+}
+
+impl SpaceSaving {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            heap: vec![],
+            positions: HashMap::new(),
+        }
+    }
+
+    fn less(&self, a: usize, b: usize) -> bool {
+        (self.heap[a].count, &self.heap[a].word) < (self.heap[b].count, &self.heap[b].word)
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        for pos in [a, b] {
+            if let Some(position) = self.positions.get_mut(self.heap[pos].word.as_str()) {
+                *position = pos;
+            }
+        }
+    }
+
+    fn sift_up(&mut self, mut i: usize) {
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if !self.less(i, parent) {
+                break;
+            }
+            self.swap(i, parent);
+            i = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut i: usize) {
+        loop {
+            let mut smallest = i;
+            for child in [2 * i + 1, 2 * i + 2] {
+                if child < self.heap.len() && self.less(child, smallest) {
+                    smallest = child;
+                }
+            }
+            if smallest == i {
+                break;
+            }
+            self.swap(i, smallest);
+            i = smallest;
+        }
+    }
+
+    fn min_count(&self) -> u64 {
+        if self.heap.len() < self.capacity {
+            0
+        } else {
+            self.heap.first().map_or(0, |counter| counter.count)
+        }
+    }
+
+    pub fn add(&mut self, word: &str) {
+        if let Some(&pos) = self.positions.get(word) {
+            self.heap[pos].count += 1;
+            self.sift_down(pos);
+        } else if self.heap.len() < self.capacity {
+            self.heap.push(Counter {
+                word: word.to_string(),
+                count: 1,
+                error: 0,
+            });
+            let last = self.heap.len() - 1;
+            self.positions.insert(word.to_string(), last);
+            self.sift_up(last);
+        } else if let Some(min) = self.heap.first() {
+            let count = min.count;
+            self.positions.remove(&min.word);
+            self.heap[0] = Counter {
+                word: word.to_string(),
+                count: count + 1,
+                error: count,
+            };
+            self.positions.insert(word.to_string(), 0);
+            self.sift_down(0);
+        }
+    }
+
+    // Mergeable summaries (Agarwal et al.): a word missing from one summary may still have been
+    // seen up to that summary's minimum count, which is added to both its count and its error.
+    pub fn merge_with(&mut self, other: Self) {
+        let (self_min, other_min) = (self.min_count(), other.min_count());
+        let mut other_counters: HashMap<String, Counter> = other
+            .heap
+            .into_iter()
+            .map(|counter| (counter.word.clone(), counter))
+            .collect();
+        let mut merged: Vec<Counter> = vec![];
+        for mut counter in self.heap.drain(..) {
+            match other_counters.remove(&counter.word) {
+                Some(other_counter) => {
+                    counter.count += other_counter.count;
+                    counter.error += other_counter.error;
+                }
+                None => {
+                    counter.count += other_min;
+                    counter.error += other_min;
+                }
+            }
+            merged.push(counter);
+        }
+        for (_, mut counter) in other_counters {
+            counter.count += self_min;
+            counter.error += self_min;
+            merged.push(counter);
+        }
+
+        merged.sort_unstable_by(|a, b| b.count.cmp(&a.count).then_with(|| a.word.cmp(&b.word)));
+        merged.truncate(self.capacity);
+        self.positions = merged
+            .iter()
+            .enumerate()
+            .map(|(pos, counter)| (counter.word.clone(), pos))
+            .collect();
+        self.heap = merged;
+        for i in (0..self.heap.len() / 2).rev() {
+            self.sift_down(i);
+        }
+    }
+
+    fn top(&self, k: usize) -> Vec<&Counter> {
+        let mut counters: Vec<&Counter> = self.heap.iter().collect();
+        counters.sort_unstable_by(|a, b| b.count.cmp(&a.count).then_with(|| a.word.cmp(&b.word)));
+        counters.truncate(k);
+        counters
+    }
+}
+
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HyperLogLog {
+    pub fn new() -> Self {
+        Self {
+            registers: vec![0; 1 << HYPERLOGLOG_PRECISION],
+        }
+    }
+
+    pub fn add(&mut self, hash: u64) {
+        let register = (hash >> (64 - HYPERLOGLOG_PRECISION)) as usize;
+        let rank = ((hash << HYPERLOGLOG_PRECISION) | (1 << (HYPERLOGLOG_PRECISION - 1)))
+            .leading_zeros() as u8
+            + 1;
+        self.registers[register] = self.registers[register].max(rank);
+    }
+
+    pub fn merge_with(&mut self, other: Self) {
+        for (register, other_register) in self.registers.iter_mut().zip(other.registers) {
+            *register = (*register).max(other_register);
+        }
+    }
+
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|&register| 2f64.powi(-(register as i32)))
+            .sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self
+            .registers
+            .iter()
+            .filter(|&&register| register == 0)
+            .count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+}
+
+pub struct ApproxWords {
+    total: u64,
+    count_min: CountMinSketch,
+    space_saving: SpaceSaving,
+    distinct: HyperLogLog,
+}
+
+impl ApproxWords {
+    pub fn new(config: &ApproxConfig) -> Self {
+        Self {
+            total: 0,
+            count_min: CountMinSketch::new(config.count_min_width),
+            space_saving: SpaceSaving::new(config.space_saving_capacity),
+            distinct: HyperLogLog::new(),
+        }
+    }
+
+    pub fn add(&mut self, word: &str) {
+        let hash = hash_word(word);
+        self.total += 1;
+        self.count_min.add(hash);
+        self.distinct.add(hash);
+        self.space_saving.add(word);
+    }
+
+    pub fn merge_with(&mut self, other: Self) {
+        self.total += other.total;
+        self.count_min.merge_with(other.count_min);
+        self.space_saving.merge_with(other.space_saving);
+        self.distinct.merge_with(other.distinct);
+    }
+
+    // Both structures only ever overestimate, so the smaller estimate is the better one;
+    // Space-Saving's error gives the lower bound.
+    fn finish(self, top_k: usize) -> (WordsFrequencyMap, ApproxSection) {
+        let mut words = WordsFrequencyMap::new();
+        let mut bounds = vec![];
+        for counter in self.space_saving.top(top_k) {
+            let max_appearances = counter
+                .count
+                .min(self.count_min.estimate(hash_word(&counter.word)));
+            let min_appearances = counter.count - counter.error;
+            words.pairs.insert(
+                counter.word.clone(),
+                max_appearances.min(u32::MAX as u64) as u32,
+            );
+            bounds.push(WordBounds {
+                word: counter.word.clone(),
+                min_appearances,
+                max_appearances,
+            });
+        }
+        let section = ApproxSection {
+            total_words: self.total,
+            distinct_words_estimate: self.distinct.estimate(),
+            max_overestimate: self.count_min.max_overestimate(self.total),
+            confidence: CountMinSketch::confidence(),
+            bounds,
+        };
+        (words, section)
+    }
+}
+
+pub struct ApproxStats {
+    words: ApproxWords,
+    low_words: ApproxWords,
+}
+
+impl ApproxStats {
+    pub fn new(config: &ApproxConfig) -> Self {
+        Self {
+            words: ApproxWords::new(config),
+            low_words: ApproxWords::new(config),
+        }
+    }
+
+    pub fn map_words(&mut self, s: &str) {
+        for word in split_words(s) {
+            self.words.add(word);
+            self.low_words.add(&word.to_ascii_lowercase());
+        }
+    }
+
+    pub fn merge_with(&mut self, other: Self) {
+        self.words.merge_with(other.words);
+        self.low_words.merge_with(other.low_words);
+    }
+
+    // Returns the top words (as written & lowercase) with their estimated counts,
+    // and the error bounds of those estimates.
+    pub fn finish(
+        self,
+        config: &ApproxConfig,
+    ) -> (WordsFrequencyMap, WordsFrequencyMap, ApproxSummary) {
+        let (words, words_section) = self.words.finish(config.top_k);
+        let (low_words, low_words_section) = self.low_words.finish(config.top_k);
+        let summary = ApproxSummary {
+            top_k: config.top_k,
+            words: words_section,
+            low_words: low_words_section,
+        };
+        (words, low_words, summary)
+    }
+}
+
+#[derive(Serialize)]
+pub struct WordBounds {
+    pub word: String,
+    pub min_appearances: u64,
+    pub max_appearances: u64,
+}
+
+#[derive(Serialize)]
+pub struct ApproxSection {
+    pub total_words: u64,
+    pub distinct_words_estimate: u64,
+    pub max_overestimate: u64,
+    pub confidence: f64,
+    #[serde(skip)]
+    pub bounds: Vec<WordBounds>,
+}
+
+#[derive(Serialize)]
+pub struct ApproxSummary {
+    pub top_k: usize,
+    pub words: ApproxSection,
+    pub low_words: ApproxSection,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_fits_in_the_memory_limit() {
+        for memory_limit in [8 << 20, 64 << 20, 1 << 30] {
+            for top_k in [1, 10, 100] {
+                let config = ApproxConfig::from_memory_limit(memory_limit, top_k).unwrap();
+                let per_map = config.count_min_width * COUNT_MIN_DEPTH * 4
+                    + (1 << HYPERLOGLOG_PRECISION)
+                    + config.space_saving_capacity * SPACE_SAVING_ENTRY_SIZE;
+                let used = SKETCHES * 2 * per_map;
+                assert!(
+                    used <= memory_limit,
+                    "{} bytes used of {}",
+                    used,
+                    memory_limit
+                );
+                // Only the rounding of the widths is left unused.
+                assert!(
+                    memory_limit - used < 1024,
+                    "{} bytes used of {}",
+                    used,
+                    memory_limit
+                );
+            }
+        }
+        assert!(ApproxConfig::from_memory_limit(256 << 10, 10).is_err());
+    }
+
+    fn counters(space_saving: &SpaceSaving) -> Vec<(&str, u64, u64)> {
+        space_saving
+            .top(space_saving.capacity)
+            .into_iter()
+            .map(|counter| (counter.word.as_str(), counter.count, counter.error))
+            .collect()
+    }
+
+    fn space_saving(capacity: usize, words: &[&str]) -> SpaceSaving {
+        let mut space_saving = SpaceSaving::new(capacity);
+        for word in words {
+            space_saving.add(word);
+        }
+        space_saving
+    }
+
+    #[test]
+    fn space_saving_merge_bounds() {
+        // Both summaries are full: a word missing from one may have been seen as many times as
+        // its least monitored word.
+        let mut a = space_saving(2, &["x", "x", "x", "y", "y"]);
+        let b = space_saving(2, &["x", "z", "z", "z", "z"]);
+        assert_eq!(counters(&a), [("x", 3, 0), ("y", 2, 0)]);
+        assert_eq!(counters(&b), [("z", 4, 0), ("x", 1, 0)]);
+        a.merge_with(b);
+        // z: 4 + a's minimum of 2, y (3 = 2 + 1, error 1) was dropped. The real counts (z 4, x 4)
+        // are within [count - error, count].
+        assert_eq!(counters(&a), [("z", 6, 2), ("x", 4, 0)]);
+
+        // A summary which isn't full monitors all its words, missing ones were never seen.
+        let mut a = space_saving(3, &["x", "x", "y"]);
+        a.merge_with(space_saving(3, &["z"]));
+        assert_eq!(counters(&a), [("x", 2, 0), ("y", 1, 0), ("z", 1, 0)]);
+
+        // Replacing the least monitored word carries its count over as the error.
+        let a = space_saving(2, &["x", "x", "y", "z"]);
+        assert_eq!(counters(&a), [("x", 2, 0), ("z", 2, 1)]);
+    }
+
+    #[test]
+    fn count_min_estimates_never_undercount() {
+        let words: Vec<(String, u64)> = (0..200).map(|i| (format!("w{}", i), i % 7 + 1)).collect();
+        let total: u64 = words.iter().map(|(_, count)| count).sum();
+        let mut sketch = CountMinSketch::new(MIN_COUNT_MIN_WIDTH);
+        for (word, count) in &words {
+            for _ in 0..*count {
+                sketch.add(hash_word(word));
+            }
+        }
+        for (word, count) in &words {
+            let estimate = sketch.estimate(hash_word(word));
+            assert!(estimate >= *count, "{}: {} < {}", word, estimate, count);
+            assert!(estimate <= count + sketch.max_overestimate(total));
+        }
+
+        // With a single column, every word collides with all the others.
+        let mut sketch = CountMinSketch::new(1);
+        for (word, count) in &words {
+            for _ in 0..*count {
+                sketch.add(hash_word(word));
+            }
+        }
+        assert_eq!(sketch.estimate(hash_word("w0")), total);
+        assert_eq!(sketch.estimate(hash_word("unseen")), total);
+    }
+
+    #[test]
+    fn top_words_bounds() {
+        let config = ApproxConfig {
+            top_k: 2,
+            count_min_width: MIN_COUNT_MIN_WIDTH,
+            space_saving_capacity: 3,
+        };
+        let texts = ["a a a a b b b c c d", "b b e e e f"];
+        let mut stats = ApproxStats::new(&config);
+        stats.map_words(texts[0]);
+        let mut other = ApproxStats::new(&config);
+        other.map_words(texts[1]);
+        stats.merge_with(other);
+        let (words, _, summary) = stats.finish(&config);
+
+        let mut real: HashMap<&str, u64> = HashMap::new();
+        for word in texts.iter().flat_map(|text| split_words(text)) {
+            *real.entry(word).or_insert(0) += 1;
+        }
+        assert_eq!(summary.words.bounds.len(), 2);
+        for bounds in &summary.words.bounds {
+            let count = real[bounds.word.as_str()];
+            assert!(
+                bounds.min_appearances <= count && count <= bounds.max_appearances,
+                "{}: {} not in {}..{}",
+                bounds.word,
+                count,
+                bounds.min_appearances,
+                bounds.max_appearances
+            );
+            assert_eq!(words.pairs[&bounds.word] as u64, bounds.max_appearances);
+        }
+        assert_eq!(summary.words.total_words, 16);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::WordsFrequencyMap;
+use anyhow::{Context, Result};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tracing::trace;
+
+// Rough memory cost of one word in a WordsFrequencyMap: the word itself, the String,
+// the count and the hash table's overhead.
+const ESTIMATED_ENTRY_SIZE: usize = 80;
+// Maximum number of runs read at once during a merge, to stay well below open files limits.
+const MAX_FAN_IN: usize = 64;
+
+pub type WordsIter<'a> = Box<dyn Iterator<Item = Result<(String, u32)>> + 'a>;
+
+// Spilling settings shared by all workers. The directory is removed when the last copy is dropped.
+#[derive(Clone)]
+pub struct SpillConfig {
+    dir: Arc<SpillDir>,
+    max_words: usize,
+}
+
+struct SpillDir {
+    path: PathBuf,
+    next_run: AtomicUsize,
+}
+
+impl Drop for SpillDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+impl SpillConfig {
+    // `instances` is the number of StatsPackages filled at once: one per worker plus the merged one.
+    pub fn new(parent_dir: &Path, threshold: usize, instances: usize) -> Result<Self> {
+        let path = parent_dir.join(format!("wiki_stats-spill-{}", std::process::id()));
+        fs::create_dir_all(&path)
+            .with_context(|| format!("Failed to create spill directory {}", path.display()))?;
+        Ok(Self {
+            dir: Arc::new(SpillDir {
+                path,
+                next_run: AtomicUsize::new(0),
+            }),
+            max_words: (threshold / instances.max(1) / ESTIMATED_ENTRY_SIZE).max(1),
+        })
+    }
+
+    pub fn is_over_threshold(
+        &self,
+        words: &WordsFrequencyMap,
+        low_words: &WordsFrequencyMap,
+    ) -> bool {
+        words.pairs.len() + low_words.pairs.len() > self.max_words
+    }
+
+    fn new_run_path(&self) -> PathBuf {
+        let run = self.dir.next_run.fetch_add(1, Ordering::Relaxed);
+        self.dir.path.join(format!("run-{}.txt", run))
+    }
+
+    // Writes the words sorted alphabetically, one "word count" pair per line, and empties the map.
+    pub fn write_run(&self, words: &mut WordsFrequencyMap) -> Result<PathBuf> {
+        let mut pairs: Vec<(String, u32)> = std::mem::take(&mut words.pairs).into_iter().collect();
+        pairs.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        let path = self.new_run_path();
+        trace!(words = pairs.len(), run = %path.display(), "Spilling words");
+        write_pairs(&path, pairs.into_iter().map(Ok))?;
+        Ok(path)
+    }
+
+    // Merges the runs in groups of MAX_FAN_IN into bigger runs, until few enough remain
+    // to be read at once by `read_runs`.
+    pub fn compact_runs(&self, mut runs: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+        while runs.len() > MAX_FAN_IN {
+            let mut merged_runs = vec![];
+            for group in runs.chunks(MAX_FAN_IN) {
+                let path = self.new_run_path();
+                trace!(runs = group.len(), run = %path.display(), "Merging runs");
+                write_pairs(&path, MergedRuns::open(group)?)?;
+                for run in group {
+                    fs::remove_file(run)?;
+                }
+                merged_runs.push(path);
+            }
+            runs = merged_runs;
+        }
+        Ok(runs)
+    }
+}
+
+// K-way merge of the runs: yields every word once, sorted, with the counts of all runs summed.
+pub fn read_runs(runs: &[PathBuf]) -> Result<WordsIter<'static>> {
+    Ok(Box::new(MergedRuns::open(runs)?))
+}
+
+// A words frequency list sorted alphabetically, which can be read several times.
+pub enum SortedWords<'a> {
+    Memory(Vec<(&'a str, u32)>),
+    Runs(&'a [PathBuf]),
+}
+
+impl<'a> SortedWords<'a> {
+    pub fn iter(&self) -> Result<WordsIter<'_>> {
+        match self {
+            SortedWords::Memory(pairs) => Ok(Box::new(
+                pairs
+                    .iter()
+                    .map(|&(word, count)| Ok((word.to_string(), count))),
+            )),
+            SortedWords::Runs(runs) => read_runs(runs),
+        }
+    }
+
+    pub fn into_words(self) -> Result<WordsIter<'a>> {
+        match self {
+            SortedWords::Memory(pairs) => Ok(Box::new(
+                pairs
+                    .into_iter()
+                    .map(|(word, count)| Ok((word.to_string(), count))),
+            )),
+            SortedWords::Runs(runs) => read_runs(runs),
+        }
+    }
+}
+
+fn write_pairs(path: &Path, pairs: impl Iterator<Item = Result<(String, u32)>>) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create spill file {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    for pair in pairs {
+        let (word, count) = pair?;
+        writeln!(writer, "{} {}", word, count)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn read_pair(lines: &mut Lines<BufReader<File>>) -> Result<Option<(String, u32)>> {
+    match lines.next() {
+        Some(line) => {
+            let line = line?;
+            let (word, count) = line
+                .rsplit_once(' ')
+                .with_context(|| format!("Malformed line in spill file: {:?}", line))?;
+            Ok(Some((word.to_string(), count.parse()?)))
+        }
+        None => Ok(None),
+    }
+}
+
+struct MergedRuns {
+    readers: Vec<Lines<BufReader<File>>>,
+    heap: BinaryHeap<Reverse<(String, usize, u32)>>,
+}
+
+impl MergedRuns {
+    fn open(runs: &[PathBuf]) -> Result<Self> {
+        let mut merged = Self {
+            readers: vec![],
+            heap: BinaryHeap::new(),
+        };
+        for (i, run) in runs.iter().enumerate() {
+            let file = File::open(run)
+                .with_context(|| format!("Failed to open spill file {}", run.display()))?;
+            merged.readers.push(BufReader::new(file).lines());
+            merged.advance(i)?;
+        }
+        Ok(merged)
+    }
+
+    fn advance(&mut self, reader: usize) -> Result<()> {
+        if let Some((word, count)) = read_pair(&mut self.readers[reader])? {
+            self.heap.push(Reverse((word, reader, count)));
+        }
+        Ok(())
+    }
+
+    fn next_pair(&mut self) -> Result<Option<(String, u32)>> {
+        let Reverse((word, reader, mut count)) = match self.heap.pop() {
+            Some(top) => top,
+            None => return Ok(None),
+        };
+        self.advance(reader)?;
+        while matches!(self.heap.peek(), Some(Reverse((next, _, _))) if *next == word) {
+            if let Some(Reverse((_, reader, other_count))) = self.heap.pop() {
+                count += other_count;
+                self.advance(reader)?;
+            }
+        }
+        Ok(Some((word, count)))
+    }
+}
+
+impl Iterator for MergedRuns {
+    type Item = Result<(String, u32)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_pair().transpose()
+    }
+}
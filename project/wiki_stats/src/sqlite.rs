@@ -0,0 +1,467 @@
+use crate::output::{OutputFile, OutputTarget};
+use crate::spill::WordsIter;
+use crate::{LongestItem, RunInfo, Section, StatsPackage, WordsFrequencyMap};
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Transaction};
+
+const SCHEMA: &str = "
+    CREATE TABLE words (word TEXT NOT NULL, count INTEGER NOT NULL);
+    CREATE TABLE words_lower (word TEXT NOT NULL, count INTEGER NOT NULL);
+    CREATE TABLE longest (kind TEXT NOT NULL PRIMARY KEY, title TEXT NOT NULL, path TEXT NOT NULL, size INTEGER NOT NULL);
+    CREATE TABLE run_info (key TEXT NOT NULL PRIMARY KEY, value TEXT NOT NULL);
+    CREATE TABLE languages (language TEXT NOT NULL PRIMARY KEY, articles INTEGER NOT NULL);
+    CREATE TABLE language_words (language TEXT NOT NULL, word TEXT NOT NULL, count INTEGER NOT NULL);
+    CREATE TABLE language_words_lower (language TEXT NOT NULL, word TEXT NOT NULL, count INTEGER NOT NULL);
+    CREATE TABLE cooccur_targets (target TEXT NOT NULL PRIMARY KEY, appearances INTEGER NOT NULL);
+    CREATE TABLE cooccurrences (target TEXT NOT NULL, word TEXT NOT NULL, count INTEGER NOT NULL, pmi REAL NOT NULL);
+    CREATE TABLE scripts (script TEXT NOT NULL PRIMARY KEY, count INTEGER NOT NULL);
+    CREATE TABLE code_points (code_point TEXT NOT NULL PRIMARY KEY, character TEXT NOT NULL, count INTEGER NOT NULL);
+    CREATE TABLE bytes (byte TEXT NOT NULL PRIMARY KEY, count INTEGER NOT NULL);
+    CREATE TABLE word_lengths (length INTEGER NOT NULL PRIMARY KEY, occurrences INTEGER NOT NULL, distinct_words INTEGER NOT NULL);
+    CREATE TABLE duplicates (article TEXT NOT NULL, duplicate TEXT NOT NULL, exact INTEGER NOT NULL, similarity REAL NOT NULL);
+";
+
+// Created after the rows are inserted, which is much faster than updating them on every insert.
+const INDEXES: &str = "
+    CREATE UNIQUE INDEX words_word ON words (word);
+    CREATE INDEX words_count ON words (count);
+    CREATE UNIQUE INDEX words_lower_word ON words_lower (word);
+    CREATE INDEX words_lower_count ON words_lower (count);
+    CREATE UNIQUE INDEX language_words_word ON language_words (language, word);
+    CREATE UNIQUE INDEX language_words_lower_word ON language_words_lower (language, word);
+    CREATE UNIQUE INDEX cooccurrences_word ON cooccurrences (target, word);
+";
+
+fn insert_words(tx: &Transaction, table: &str, words: WordsIter) -> Result<()> {
+    let mut statement = tx.prepare(&format!(
+        "INSERT INTO {} (word, count) VALUES (?1, ?2)",
+        table
+    ))?;
+    for pair in words {
+        let (word, count) = pair?;
+        statement.execute(params![word, count])?;
+    }
+    Ok(())
+}
+
+fn insert_language_words(
+    tx: &Transaction,
+    table: &str,
+    language: &str,
+    words: &WordsFrequencyMap,
+) -> Result<()> {
+    let mut statement = tx.prepare(&format!(
+        "INSERT INTO {} (language, word, count) VALUES (?1, ?2, ?3)",
+        table
+    ))?;
+    for (word, count) in words.sorted_pairs() {
+        statement.execute(params![language, word, count])?;
+    }
+    Ok(())
+}
+
+fn insert_longest_item(tx: &Transaction, kind: &str, item: &LongestItem) -> Result<()> {
+    tx.execute(
+        "INSERT INTO longest (kind, title, path, size) VALUES (?1, ?2, ?3, ?4)",
+        params![kind, item.title, item.path, item.size as i64],
+    )?;
+    Ok(())
+}
+
+pub fn write_stats_to_file_sqlite(
+    stats: &mut StatsPackage,
+    sections: &[Section],
+    run_info: &RunInfo,
+    target: &OutputTarget,
+) -> Result<()> {
+    let mut stats_file = OutputFile::create(target)?;
+    let mut connection =
+        Connection::open(stats_file.database_path()).context("Failed to create output file")?;
+    let tx = connection.transaction()?;
+    tx.execute_batch(SCHEMA)?;
+
+    // Tables (& run_info rows) of the sections left out stay empty.
+    for section in sections {
+        match section {
+            Section::Words => insert_words(&tx, "words", stats.words(false)?)?,
+            Section::Lower => insert_words(&tx, "words_lower", stats.words(true)?)?,
+            Section::LongestArticle => insert_longest_item(&tx, "article", &stats.long_art)?,
+            Section::LongestTitle => insert_longest_item(&tx, "title", &stats.long_title)?,
+            // Their tables have a fixed order, they're written below.
+            _ => {}
+        }
+    }
+    let selected = |section| sections.contains(&section);
+    let distribution = (stats.distribution.as_ref()).filter(|_| selected(Section::Distribution));
+    let approx_summary = (stats.approx_summary.as_ref()).filter(|_| selected(Section::Approx));
+    let cooccur_summary = (stats.cooccur_summary.as_ref()).filter(|_| selected(Section::Cooccur));
+    let char_stats = (stats.char_stats.as_ref()).filter(|_| selected(Section::Chars));
+    let dedup_report = (stats.dedup_report.as_ref()).filter(|_| selected(Section::Duplicates));
+
+    if selected(Section::Languages) {
+        for (language, words) in stats.sorted_languages() {
+            tx.execute(
+                "INSERT INTO languages (language, articles) VALUES (?1, ?2)",
+                params![language, words.articles],
+            )?;
+            insert_language_words(&tx, "language_words", language, &words.words_freq)?;
+            insert_language_words(&tx, "language_words_lower", language, &words.low_words_freq)?;
+        }
+    }
+
+    if let Some(cooccurrences) = cooccur_summary {
+        for target in cooccurrences {
+            tx.execute(
+                "INSERT INTO cooccur_targets (target, appearances) VALUES (?1, ?2)",
+                params![target.target, target.appearances],
+            )?;
+            for neighbor in &target.neighbors {
+                tx.execute(
+                    "INSERT INTO cooccurrences (target, word, count, pmi) VALUES (?1, ?2, ?3, ?4)",
+                    params![target.target, neighbor.word, neighbor.count, neighbor.pmi],
+                )?;
+            }
+        }
+    }
+
+    let char_summary = char_stats.map(|char_stats| char_stats.summary());
+    if let Some(summary) = &char_summary {
+        for script in &summary.scripts {
+            tx.execute(
+                "INSERT INTO scripts (script, count) VALUES (?1, ?2)",
+                params![script.script, script.appearances as i64],
+            )?;
+        }
+        for code_point in &summary.code_points {
+            tx.execute(
+                "INSERT INTO code_points (code_point, character, count) VALUES (?1, ?2, ?3)",
+                params![
+                    code_point.code_point,
+                    code_point.character,
+                    code_point.appearances as i64
+                ],
+            )?;
+        }
+        for byte in &summary.bytes {
+            tx.execute(
+                "INSERT INTO bytes (byte, count) VALUES (?1, ?2)",
+                params![byte.byte, byte.appearances as i64],
+            )?;
+        }
+    }
+
+    if let Some(distribution) = distribution {
+        for length in &distribution.word_lengths {
+            tx.execute(
+                "INSERT INTO word_lengths (length, occurrences, distinct_words) VALUES (?1, ?2, ?3)",
+                params![
+                    length.length as i64,
+                    length.occurrences as i64,
+                    length.distinct_words as i64
+                ],
+            )?;
+        }
+    }
+
+    if let Some(report) = dedup_report {
+        for cluster in &report.clusters {
+            for duplicate in &cluster.duplicates {
+                tx.execute(
+                    "INSERT INTO duplicates (article, duplicate, exact, similarity) VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        cluster.article,
+                        duplicate.path,
+                        duplicate.exact,
+                        duplicate.similarity
+                    ],
+                )?;
+            }
+        }
+    }
+
+    let mut run_info_rows = vec![
+        ("version", env!("CARGO_PKG_VERSION").to_string()),
+        ("input", run_info.input.clone()),
+        ("input_size", run_info.input_size.to_string()),
+        ("files_total", run_info.files_total.to_string()),
+        ("files_processed", run_info.files_processed.to_string()),
+        ("started_at", run_info.started_at.to_string()),
+        ("processing_millis", run_info.processing_millis.to_string()),
+    ];
+    if let Some(summary) = approx_summary {
+        run_info_rows.extend([
+            ("approx_top_k", summary.top_k.to_string()),
+            ("approx_total_words", summary.words.total_words.to_string()),
+            (
+                "approx_distinct_words",
+                summary.words.distinct_words_estimate.to_string(),
+            ),
+            (
+                "approx_max_overestimate",
+                summary.words.max_overestimate.to_string(),
+            ),
+            (
+                "approx_lower_total_words",
+                summary.low_words.total_words.to_string(),
+            ),
+            (
+                "approx_lower_distinct_words",
+                summary.low_words.distinct_words_estimate.to_string(),
+            ),
+            (
+                "approx_lower_max_overestimate",
+                summary.low_words.max_overestimate.to_string(),
+            ),
+            ("approx_confidence", summary.words.confidence.to_string()),
+        ]);
+    }
+    if let Some(summary) = &char_summary {
+        run_info_rows.extend([
+            ("total_characters", summary.total_characters.to_string()),
+            (
+                "distinct_characters",
+                summary.distinct_characters.to_string(),
+            ),
+            ("control_characters", summary.control_characters.to_string()),
+            (
+                "replacement_characters",
+                summary.replacement_characters.to_string(),
+            ),
+            ("total_bytes", summary.total_bytes.to_string()),
+        ]);
+    }
+    if let Some(distribution) = distribution {
+        if let (Some(exponent), Some(r_squared)) =
+            (distribution.zipf_exponent, distribution.zipf_r_squared)
+        {
+            run_info_rows.extend([
+                ("zipf_exponent", exponent.to_string()),
+                ("zipf_r_squared", r_squared.to_string()),
+            ]);
+        }
+    }
+    if let Some(report) = dedup_report {
+        run_info_rows.extend([
+            (
+                "dedup_articles_checked",
+                report.articles_checked.to_string(),
+            ),
+            (
+                "dedup_exact_duplicates",
+                report.exact_duplicates.to_string(),
+            ),
+            ("dedup_near_duplicates", report.near_duplicates.to_string()),
+            ("dedup_threshold", report.threshold.to_string()),
+            (
+                "dedup_excluded_from_counts",
+                report.excluded_from_counts.to_string(),
+            ),
+        ]);
+    }
+    for (key, value) in run_info_rows {
+        tx.execute(
+            "INSERT INTO run_info (key, value) VALUES (?1, ?2)",
+            params![key, value],
+        )?;
+    }
+
+    tx.execute_batch(INDEXES)?;
+    tx.commit()?;
+    connection.close().map_err(|(_, e)| e)?;
+    stats_file.finish()
+}
+
+fn read_words(connection: &Connection, table: &str) -> Result<WordsFrequencyMap> {
+    let mut statement = connection.prepare(&format!("SELECT word, count FROM {}", table))?;
+    let pairs = statement
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(WordsFrequencyMap { pairs })
+}
+
+fn read_language_words(connection: &Connection, stats: &mut StatsPackage) -> Result<()> {
+    let mut statement = connection.prepare("SELECT language, articles FROM languages")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        stats.languages.entry(row.get(0)?).or_default().articles = row.get(1)?;
+    }
+    for (table, lowercase) in [("language_words", false), ("language_words_lower", true)] {
+        let mut statement =
+            connection.prepare(&format!("SELECT language, word, count FROM {}", table))?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let language_words = stats.languages.entry(row.get(0)?).or_default();
+            let words = if lowercase {
+                &mut language_words.low_words_freq
+            } else {
+                &mut language_words.words_freq
+            };
+            words.pairs.insert(row.get(1)?, row.get(2)?);
+        }
+    }
+    Ok(())
+}
+
+// Missing when the section was left out of the output.
+fn read_longest_item(connection: &Connection, kind: &str) -> Result<LongestItem> {
+    Ok(connection
+        .query_row(
+            "SELECT title, path, size FROM longest WHERE kind = ?1",
+            [kind],
+            |row| {
+                Ok(LongestItem {
+                    title: row.get(0)?,
+                    path: row.get(1)?,
+                    size: row.get::<_, i64>(2)? as usize,
+                })
+            },
+        )
+        .optional()?
+        .unwrap_or_default())
+}
+
+pub fn read_stats_from_file_sqlite(stats_file_path: &str) -> Result<StatsPackage> {
+    let connection = Connection::open_with_flags(stats_file_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .context("Failed to open stats database")?;
+    let mut stats = StatsPackage {
+        words_freq: read_words(&connection, "words")?,
+        low_words_freq: read_words(&connection, "words_lower")?,
+        long_art: read_longest_item(&connection, "article")?,
+        long_title: read_longest_item(&connection, "title")?,
+        ..StatsPackage::new()
+    };
+    read_language_words(&connection, &mut stats)?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ValueEnum;
+    use std::collections::BTreeMap;
+
+    fn words(pairs: &[(&str, u32)]) -> WordsFrequencyMap {
+        WordsFrequencyMap {
+            pairs: pairs
+                .iter()
+                .map(|&(word, count)| (word.to_string(), count))
+                .collect(),
+        }
+    }
+
+    fn sorted(words: &WordsFrequencyMap) -> BTreeMap<&str, u32> {
+        words
+            .pairs
+            .iter()
+            .map(|(word, &count)| (word.as_str(), count))
+            .collect()
+    }
+
+    fn write(stats: &mut StatsPackage, sections: &[Section], path: &str) {
+        let target = OutputTarget {
+            path: path.to_string(),
+            no_clobber: false,
+            compression: None,
+        };
+        write_stats_to_file_sqlite(stats, sections, &RunInfo::default(), &target).unwrap();
+    }
+
+    fn columns(connection: &Connection, table: &str) -> Vec<String> {
+        let mut statement = connection
+            .prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))
+            .unwrap();
+        let columns = statement
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        columns
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stats.db").to_string_lossy().to_string();
+        let mut stats = StatsPackage {
+            words_freq: words(&[("River", 2), ("the", 3)]),
+            low_words_freq: words(&[("river", 2), ("the", 3)]),
+            long_art: LongestItem {
+                title: "River".to_string(),
+                path: "a.json/1".to_string(),
+                size: 42,
+            },
+            long_title: LongestItem {
+                title: "The river".to_string(),
+                path: "a.json/2".to_string(),
+                size: 9,
+            },
+            ..StatsPackage::new()
+        };
+        let english = stats.languages.entry("en".to_string()).or_default();
+        english.articles = 2;
+        english.words_freq = words(&[("River", 2)]);
+        english.low_words_freq = words(&[("river", 2)]);
+        stats.compute_distribution(false).unwrap();
+        write(&mut stats, Section::value_variants(), &path);
+
+        let connection = Connection::open(&path).unwrap();
+        assert_eq!(columns(&connection, "words"), ["word", "count"]);
+        assert_eq!(columns(&connection, "words_lower"), ["word", "count"]);
+        assert_eq!(
+            columns(&connection, "longest"),
+            ["kind", "title", "path", "size"]
+        );
+        assert_eq!(columns(&connection, "languages"), ["language", "articles"]);
+        assert_eq!(
+            columns(&connection, "language_words"),
+            ["language", "word", "count"]
+        );
+        assert_eq!(
+            columns(&connection, "word_lengths"),
+            ["length", "occurrences", "distinct_words"]
+        );
+        let lengths: Vec<(i64, i64, i64)> = connection
+            .prepare("SELECT * FROM word_lengths ORDER BY length")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(lengths, [(3, 3, 1), (5, 2, 1)]);
+        let version: String = connection
+            .query_row(
+                "SELECT value FROM run_info WHERE key = 'version'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(version, env!("CARGO_PKG_VERSION"));
+        drop(connection);
+
+        // Writing leaves the stats as they were, so they're compared with what is read back.
+        let read = read_stats_from_file_sqlite(&path).unwrap();
+        assert_eq!(sorted(&read.words_freq), sorted(&stats.words_freq));
+        assert_eq!(sorted(&read.low_words_freq), sorted(&stats.low_words_freq));
+        for (item, read_item) in [
+            (&stats.long_art, &read.long_art),
+            (&stats.long_title, &read.long_title),
+        ] {
+            assert_eq!(
+                (&read_item.title, &read_item.path, read_item.size),
+                (&item.title, &item.path, item.size)
+            );
+        }
+        let english = &read.languages["en"];
+        assert_eq!(english.articles, 2);
+        assert_eq!(sorted(&english.words_freq), [("River", 2)].into());
+        assert_eq!(sorted(&english.low_words_freq), [("river", 2)].into());
+
+        // The tables of the sections left out are empty.
+        write(&mut stats, &[Section::Lower], &path);
+        let read = read_stats_from_file_sqlite(&path).unwrap();
+        assert!(read.words_freq.pairs.is_empty());
+        assert_eq!(sorted(&read.low_words_freq), sorted(&stats.low_words_freq));
+        assert!(read.long_art.title.is_empty());
+        assert!(read.languages.is_empty());
+    }
+}
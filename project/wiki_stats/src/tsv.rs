@@ -0,0 +1,119 @@
+use crate::output::{OutputFile, OutputTarget};
+use crate::{LongestItem, Section, StatsPackage};
+use anyhow::{bail, Context, Result};
+use std::io::{BufWriter, Write};
+
+// One row per line, the first column being the section it belongs to, so that the output can be
+// filtered & sorted by line-oriented tools:
+//   words	<word>	<count>
+//   lower	<word>	<count>
+//   longest-article	<title>	<path>	<size>
+//   longest-title	<title>	<path>	<size>
+fn section_name(section: Section) -> &'static str {
+    match section {
+        Section::Words => "words",
+        Section::Lower => "lower",
+        Section::LongestArticle => "longest-article",
+        Section::LongestTitle => "longest-title",
+        Section::Distribution => "distribution",
+        Section::Approx => "approx",
+        Section::Languages => "languages",
+        Section::Cooccur => "cooccur",
+        Section::Chars => "chars",
+        Section::Duplicates => "duplicates",
+    }
+}
+
+// Words never contain tabs nor line breaks, but titles may.
+fn escape(s: &str) -> String {
+    s.replace(['\t', '\n', '\r'], " ")
+}
+
+fn write_longest_item(w: &mut impl Write, section: Section, item: &LongestItem) -> Result<()> {
+    writeln!(
+        w,
+        "{}\t{}\t{}\t{}",
+        section_name(section),
+        escape(&item.title),
+        escape(&item.path),
+        item.size
+    )?;
+    Ok(())
+}
+
+pub fn write_stats_to_file_tsv(
+    stats: &mut StatsPackage,
+    sections: &[Section],
+    target: &OutputTarget,
+) -> Result<()> {
+    let mut writer = BufWriter::new(OutputFile::create(target)?);
+
+    for &section in sections {
+        match section {
+            Section::Words | Section::Lower => {
+                for pair in stats.words(section == Section::Lower)? {
+                    let (word, count) = pair?;
+                    writeln!(writer, "{}\t{}\t{}", section_name(section), word, count)?;
+                }
+            }
+            Section::LongestArticle => write_longest_item(&mut writer, section, &stats.long_art)?,
+            Section::LongestTitle => write_longest_item(&mut writer, section, &stats.long_title)?,
+            // Only the lists & longest items have rows.
+            _ => {}
+        }
+    }
+
+    writer.into_inner().map_err(|e| e.into_error())?.finish()
+}
+
+pub fn is_tsv(text: &str) -> bool {
+    [
+        Section::Words,
+        Section::Lower,
+        Section::LongestArticle,
+        Section::LongestTitle,
+    ]
+    .into_iter()
+    .any(|section| {
+        text.strip_prefix(section_name(section))
+            .is_some_and(|rest| rest.starts_with('\t'))
+    })
+}
+
+fn read_longest_item(fields: &[&str]) -> Result<LongestItem> {
+    match fields {
+        [title, path, size] => Ok(LongestItem {
+            title: title.to_string(),
+            path: path.to_string(),
+            size: size.parse()?,
+        }),
+        _ => bail!("Malformed longest item in stats file: {:?}", fields),
+    }
+}
+
+pub fn read_stats_tsv(text: &str) -> Result<StatsPackage> {
+    let mut stats = StatsPackage::new();
+    for line in text.lines().filter(|line| !line.is_empty()) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields[0] {
+            "words" | "lower" => {
+                let [_, word, count] = fields[..] else {
+                    bail!("Malformed line in stats file: {:?}", line);
+                };
+                let words = if fields[0] == "lower" {
+                    &mut stats.low_words_freq
+                } else {
+                    &mut stats.words_freq
+                };
+                let count = count
+                    .parse()
+                    .with_context(|| format!("Malformed line in stats file: {:?}", line))?;
+                words.pairs.insert(word.to_string(), count);
+            }
+            "longest-article" => stats.long_art = read_longest_item(&fields[1..])?,
+            "longest-title" => stats.long_title = read_longest_item(&fields[1..])?,
+            _ => bail!("Malformed line in stats file: {:?}", line),
+        }
+    }
+    Ok(stats)
+}
@@ -0,0 +1,211 @@
+use crate::cli::WatchArguments;
+use crate::output::{self, OutputFile, OutputTarget};
+use crate::{
+    analyze_archive, archive, reader, write_stats, AnalysisOptions, OutputFormat, RunInfo, Section,
+    StatsPackage,
+};
+use anyhow::{bail, Context, Result};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+// Names of the archives already merged into the cumulative output, and of those which couldn't
+// be analyzed (e.g. a malformed entry), which are never retried.
+// Each new output is first written to a staged file, then committed by saving the state with
+// `staged` set, and only then moved into place: a staged file left by a crash is moved into place
+// at restart if it was committed, and removed otherwise, so that no archive is counted twice.
+#[derive(Serialize, Deserialize, Default)]
+struct WatchState {
+    archives: BTreeSet<String>,
+    #[serde(default)]
+    failed: BTreeSet<String>,
+    #[serde(default)]
+    staged: bool,
+}
+
+impl WatchState {
+    fn load(path: &str) -> Result<Self> {
+        if !Path::new(path).exists() {
+            return Ok(Self::default());
+        }
+        let contents = fs::read(path).with_context(|| format!("Failed to read {}", path))?;
+        serde_json::from_slice(&contents).with_context(|| format!("Failed to parse {}", path))
+    }
+
+    // Written like the outputs (temp file & rename).
+    fn save(&self, path: &str) -> Result<()> {
+        let mut state_file = OutputFile::create(&OutputTarget {
+            path: path.to_string(),
+            no_clobber: false,
+            compression: None,
+        })?;
+        serde_json::to_writer_pretty(&mut state_file, self)?;
+        state_file.write_all(b"\n")?;
+        state_file.finish()
+    }
+}
+
+// The .zip files of the directory not merged yet, by name.
+fn new_archives(dir: &Path, state: &WatchState) -> Result<Vec<(String, PathBuf)>> {
+    let mut archives = vec![];
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => continue,
+        };
+        if name.ends_with(".zip")
+            && path.is_file()
+            && !state.archives.contains(&name)
+            && !state.failed.contains(&name)
+        {
+            archives.push((name, path));
+        }
+    }
+    archives.sort_unstable();
+    Ok(archives)
+}
+
+pub fn run_watch(args: WatchArguments) -> Result<()> {
+    let target = args.output.target();
+    if target.is_stdout() {
+        bail!("watch needs an output file, it can't write to the standard output");
+    }
+    if target.no_clobber {
+        bail!("watch rewrites its output after each archive, --no-clobber can't be used");
+    }
+    let state_path = args
+        .state
+        .clone()
+        .unwrap_or_else(|| format!("{}.watch.json", target.path));
+    let mut state = WatchState::load(&state_path)?;
+    let staged_path = format!("{}.watch.new", target.path);
+    if Path::new(&staged_path).exists() {
+        if state.staged {
+            info!("Moving the output staged before the last stop into place");
+            output::rename(Path::new(&staged_path), Path::new(&target.path))?;
+        } else {
+            fs::remove_file(&staged_path)
+                .with_context(|| format!("Failed to remove {}", staged_path))?;
+        }
+    }
+    if state.staged {
+        state.staged = false;
+        state.save(&state_path)?;
+    }
+    if state.archives.is_empty() && Path::new(&target.path).exists() {
+        bail!(
+            "Output file {} already exists but isn't listed in {}, refusing to overwrite it",
+            target.path,
+            state_path
+        );
+    }
+
+    let threads = args
+        .threads
+        .map_or_else(num_cpus::get, |threads| threads as usize);
+    let format = args.output.format();
+    let sections = args.output.sections();
+    // They would be lost at restart, when the cumulative stats are read back.
+    if args.languages
+        && sections.contains(&Section::Languages)
+        && matches!(format, OutputFormat::Index | OutputFormat::Tsv)
+    {
+        bail!(
+            "'index' & 'tsv' outputs don't keep the languages, --languages can't be used with them"
+        );
+    }
+    let options = AnalysisOptions {
+        detect_languages: args.languages && sections.contains(&Section::Languages),
+        strip_markup: args.strip_markup,
+        skip_words: !sections.contains(&Section::Words),
+        // The distribution is fitted on the lowercase list without the as-written one.
+        skip_lower: !sections.contains(&Section::Lower)
+            && !sections.contains(&Section::Distribution),
+        skip_longest_article: !sections.contains(&Section::LongestArticle),
+        skip_longest_title: !sections.contains(&Section::LongestTitle),
+        skip_distribution: !sections.contains(&Section::Distribution),
+        ..AnalysisOptions::default()
+    };
+
+    // Read once: each archive is then merged into the stats kept in memory.
+    let mut cumulative_stats = if state.archives.is_empty() {
+        StatsPackage::new()
+    } else {
+        reader::read_stats_from_file(&target.path)?
+    };
+    info!(
+        "Watching {} ({} archives already merged into {}, {} skipped)",
+        args.dir.display(),
+        state.archives.len(),
+        target.path,
+        state.failed.len()
+    );
+    loop {
+        for (name, path) in new_archives(&args.dir, &state)? {
+            // An archive still being copied has no central directory yet.
+            if let Err(e) = archive::open(&path.to_string_lossy()) {
+                warn!("Skipping {} until the next scan: {:#}", name, e);
+                continue;
+            }
+            let (stats, run_info) =
+                match analyze_archive(&path.to_string_lossy(), &options, threads, false) {
+                    Ok(analysis) => analysis,
+                    Err(e) => {
+                        warn!("Skipping {} for good: {:#}", name, e);
+                        state.failed.insert(name);
+                        state.save(&state_path)?;
+                        continue;
+                    }
+                };
+            cumulative_stats.merge_with(stats);
+            state.archives.insert(name.clone());
+            if !options.skip_distribution {
+                cumulative_stats
+                    .compute_distribution(cumulative_stats.words_freq.pairs.is_empty())?;
+            }
+
+            let run_info = RunInfo {
+                input: args.dir.display().to_string(),
+                input_size: state
+                    .archives
+                    .iter()
+                    .map(|archive| fs::metadata(args.dir.join(archive)).map_or(0, |m| m.len()))
+                    .sum(),
+                files_total: state.archives.len(),
+                files_processed: state.archives.len(),
+                ..run_info
+            };
+            write_stats(
+                &mut cumulative_stats,
+                format,
+                &sections,
+                &run_info,
+                &OutputTarget {
+                    path: staged_path.clone(),
+                    ..target.clone()
+                },
+            )?;
+            state.staged = true;
+            state.save(&state_path)?;
+            output::rename(Path::new(&staged_path), Path::new(&target.path))?;
+            state.staged = false;
+            state.save(&state_path)?;
+            info!(
+                "Merged {} into {} ({} archives)",
+                name,
+                target.path,
+                state.archives.len()
+            );
+        }
+        if args.once {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_secs(args.interval));
+    }
+}
@@ -0,0 +1,433 @@
+mod common;
+
+use common::{wiki_stats, write_zip};
+use std::collections::BTreeMap;
+use std::fs;
+use std::process::Output;
+
+#[derive(Debug, Default, PartialEq)]
+struct Stats {
+    words: BTreeMap<String, u32>,
+    lower: BTreeMap<String, u32>,
+    // (title, path, size)
+    longest_article: Option<(String, String, usize)>,
+    longest_title: Option<(String, String, usize)>,
+}
+
+fn longest_item(fields: &[&str]) -> Option<(String, String, usize)> {
+    Some((
+        fields[1].to_string(),
+        fields[2].to_string(),
+        fields[3].parse().unwrap(),
+    ))
+}
+
+fn parse_tsv(text: &str) -> Stats {
+    let mut stats = Stats::default();
+    for line in text.lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields[0] {
+            "words" => {
+                stats
+                    .words
+                    .insert(fields[1].to_string(), fields[2].parse().unwrap());
+            }
+            "lower" => {
+                stats
+                    .lower
+                    .insert(fields[1].to_string(), fields[2].parse().unwrap());
+            }
+            "longest-article" => stats.longest_article = longest_item(&fields),
+            "longest-title" => stats.longest_title = longest_item(&fields),
+            _ => panic!("unexpected line: {:?}", line),
+        }
+    }
+    stats
+}
+
+fn run(entries: &[(&str, &str)]) -> (Output, Option<Stats>) {
+    let dir = tempfile::tempdir().unwrap();
+    let dataset = dir.path().join("dataset.zip");
+    write_zip(&dataset, entries);
+    let output_path = dir.path().join("stats.tsv");
+    let output = wiki_stats()
+        .arg("analyze")
+        .arg("--input")
+        .arg(&dataset)
+        .arg("--output")
+        .arg(&output_path)
+        .args(["--format", "tsv", "--threads", "2"])
+        .output()
+        .unwrap();
+    let stats = fs::read_to_string(&output_path)
+        .ok()
+        .map(|text| parse_tsv(&text));
+    (output, stats)
+}
+
+fn analyze(entries: &[(&str, &str)]) -> Stats {
+    let (output, stats) = run(entries);
+    assert!(
+        output.status.success(),
+        "analyze failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    stats.unwrap()
+}
+
+fn counts(pairs: &[(&str, u32)]) -> BTreeMap<String, u32> {
+    pairs
+        .iter()
+        .map(|&(word, count)| (word.to_string(), count))
+        .collect()
+}
+
+fn item(title: &str, path: &str, size: usize) -> Option<(String, String, usize)> {
+    Some((title.to_string(), path.to_string(), size))
+}
+
+#[test]
+fn counts_titles_and_texts() {
+    let stats = analyze(&[(
+        "a.json",
+        r#"[{"id": "1", "title": "The Cat", "text": "the cat sat. The end"},
+            {"id": "2", "title": "Dog", "text": "it's a dog's life"}]"#,
+    )]);
+    assert_eq!(
+        stats,
+        Stats {
+            words: counts(&[
+                ("Cat", 1),
+                ("Dog", 1),
+                ("The", 2),
+                ("a", 1),
+                ("cat", 1),
+                ("dog's", 1),
+                ("end", 1),
+                ("it's", 1),
+                ("life", 1),
+                ("sat", 1),
+                ("the", 1),
+            ]),
+            lower: counts(&[
+                ("a", 1),
+                ("cat", 2),
+                ("dog", 1),
+                ("dog's", 1),
+                ("end", 1),
+                ("it's", 1),
+                ("life", 1),
+                ("sat", 1),
+                ("the", 3),
+            ]),
+            longest_article: item("The Cat", "a.json/1", 20),
+            longest_title: item("The Cat", "a.json/1", 7),
+        }
+    );
+}
+
+#[test]
+fn reads_nested_directories_and_skips_other_entries() {
+    let stats = analyze(&[
+        ("top/", ""),
+        ("top/nested/", ""),
+        (
+            "top/nested/deep.json",
+            r#"[{"id": "7", "title": "Deep", "text": "down here"}]"#,
+        ),
+        (
+            "top/shallow.json",
+            r#"[{"id": "3", "title": "Up", "text": "up there"}]"#,
+        ),
+        ("README.txt", "not counted"),
+        (
+            "top/backup.json.bak",
+            r#"[{"id": "9", "title": "Old", "text": "ignored"}]"#,
+        ),
+    ]);
+    assert_eq!(
+        stats.lower,
+        counts(&[
+            ("deep", 1),
+            ("down", 1),
+            ("here", 1),
+            ("there", 1),
+            ("up", 2)
+        ])
+    );
+    assert_eq!(
+        stats.longest_article,
+        item("Deep", "top/nested/deep.json/7", 9)
+    );
+    assert_eq!(
+        stats.longest_title,
+        item("Deep", "top/nested/deep.json/7", 4)
+    );
+}
+
+#[test]
+fn empty_arrays() {
+    let stats = analyze(&[
+        ("empty.json", "[]"),
+        ("one.json", r#"[{"id": "1", "title": "T", "text": "word"}]"#),
+        ("also-empty.json", " [ ] "),
+    ]);
+    assert_eq!(stats.words, counts(&[("T", 1), ("word", 1)]));
+    assert_eq!(stats.longest_article, item("T", "one.json/1", 4));
+
+    // Nothing to count at all.
+    let stats = analyze(&[("empty.json", "[]")]);
+    assert_eq!(
+        stats,
+        Stats {
+            longest_article: item("", "", 0),
+            longest_title: item("", "", 0),
+            ..Stats::default()
+        }
+    );
+}
+
+#[test]
+fn unicode() {
+    let stats = analyze(&[(
+        "ü/ünï.json",
+        r#"[{"id": "é", "title": "Café", "text": "naïve Straße 東京 l'été ÉCOLE École — «quoted»"}]"#,
+    )]);
+    assert_eq!(
+        stats.words,
+        counts(&[
+            ("Café", 1),
+            ("Straße", 1),
+            ("ÉCOLE", 1),
+            ("École", 1),
+            ("l'été", 1),
+            ("naïve", 1),
+            ("quoted", 1),
+            ("東京", 1),
+        ])
+    );
+    // Words are lowercased as ASCII only.
+    assert_eq!(
+        stats.lower,
+        counts(&[
+            ("café", 1),
+            ("l'été", 1),
+            ("naïve", 1),
+            ("quoted", 1),
+            ("straße", 1),
+            ("École", 2),
+            ("東京", 1),
+        ])
+    );
+    // Sizes are in bytes.
+    assert_eq!(
+        stats.longest_article,
+        item(
+            "Café",
+            "ü/ünï.json/é",
+            "naïve Straße 東京 l'été ÉCOLE École — «quoted»".len()
+        )
+    );
+    assert_eq!(stats.longest_title, item("Café", "ü/ünï.json/é", 5));
+}
+
+#[test]
+fn longest_items_ties() {
+    // Within a file the first article wins, across files the smallest path.
+    let stats = analyze(&[
+        (
+            "b.json",
+            r#"[{"id": "1", "title": "bb", "text": "same"}, {"id": "2", "title": "cc", "text": "four"}]"#,
+        ),
+        (
+            "a.json",
+            r#"[{"id": "9", "title": "aa", "text": "size"}, {"id": "1", "title": "x", "text": "abc"}]"#,
+        ),
+    ]);
+    assert_eq!(stats.longest_article, item("aa", "a.json/9", 4));
+    assert_eq!(stats.longest_title, item("aa", "a.json/9", 2));
+}
+
+#[test]
+fn malformed_files_fail_the_run() {
+    for malformed in [
+        r#"[{"id": "1", "title": "T", "text": "#,
+        r#"{"id": "1", "title": "T", "text": "not in an array"}"#,
+        r#"[{"id": 1, "title": "T", "text": "id is not a string"}]"#,
+        r#"[{"id": "1", "text": "no title"}]"#,
+        "",
+    ] {
+        let (output, stats) = run(&[
+            (
+                "good.json",
+                r#"[{"id": "1", "title": "T", "text": "fine"}]"#,
+            ),
+            ("bad.json", malformed),
+        ]);
+        assert!(!output.status.success(), "{:?} was accepted", malformed);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("couldn't process"));
+        // No partial output is left behind.
+        assert!(stats.is_none());
+    }
+}
+
+#[test]
+fn not_a_zip_archive() {
+    let dir = tempfile::tempdir().unwrap();
+    let dataset = dir.path().join("dataset.zip");
+    fs::write(&dataset, "not a zip").unwrap();
+    let output = wiki_stats()
+        .arg("analyze")
+        .arg("--input")
+        .arg(&dataset)
+        .arg("--output")
+        .arg(dir.path().join("stats.tsv"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn per_article_rows() {
+    let dir = tempfile::tempdir().unwrap();
+    let dataset = dir.path().join("dataset.zip");
+    write_zip(
+        &dataset,
+        &[
+            (
+                "b.json",
+                r#"[{"id": "2", "title": "Say \"hi\", twice", "text": "naïve café, café"},
+                    {"id": "3", "title": "Empty", "text": ""}]"#,
+            ),
+            ("a.json", r#"[{"id": "1", "title": "A", "text": "a bb a"}]"#),
+        ],
+    );
+    let per_article = dir.path().join("articles.csv");
+    let output = wiki_stats()
+        .arg("analyze")
+        .arg("--input")
+        .arg(&dataset)
+        .arg("--output")
+        .arg(dir.path().join("stats.tsv"))
+        .arg("--per-article")
+        .arg(&per_article)
+        .args(["--format", "tsv", "--threads", "2"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "analyze failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    // In the order of the archive, the title quoted as CSV, sizes in bytes & in characters.
+    assert_eq!(
+        fs::read_to_string(&per_article).unwrap(),
+        "path,title,bytes,characters,tokens,unique_tokens,average_word_length\n\
+         b.json/2,\"Say \"\"hi\"\", twice\",19,16,3,2,4.33\n\
+         b.json/3,Empty,0,0,0,0,0.00\n\
+         a.json/1,A,6,6,3,2,1.33\n"
+    );
+}
+
+#[test]
+fn unwritable_output_fails_the_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let dataset = dir.path().join("dataset.zip");
+    write_zip(
+        &dataset,
+        &[("a.json", r#"[{"id": "1", "title": "T", "text": "word"}]"#)],
+    );
+    let output = wiki_stats()
+        .arg("analyze")
+        .arg("--input")
+        .arg(&dataset)
+        .arg("--output")
+        .arg(dir.path().join("missing").join("stats.tsv"))
+        .args(["--format", "tsv"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("writing output"));
+}
+
+#[test]
+fn sections_select_and_order_every_section() {
+    let dir = tempfile::tempdir().unwrap();
+    let dataset = dir.path().join("dataset.zip");
+    write_zip(
+        &dataset,
+        &[("a.json", r#"[{"id": "1", "title": "T", "text": "ab ab"}]"#)],
+    );
+    let output_path = dir.path().join("stats.txt");
+    let output = wiki_stats()
+        .arg("analyze")
+        .arg("--input")
+        .arg(&dataset)
+        .arg("--output")
+        .arg(&output_path)
+        .args(["--plain", "--char-stats", "--languages"])
+        .args(["--sections", "chars,longest-title,distribution,cooccur"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "analyze failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let text = fs::read_to_string(&output_path).unwrap();
+    let headers: Vec<&str> = text
+        .lines()
+        .filter_map(|line| line.strip_prefix('\t'))
+        .collect();
+    // Languages were left out, and co-occurrences not enabled.
+    assert_eq!(
+        headers,
+        [
+            "Characters",
+            "Scripts",
+            "Code points",
+            "Bytes",
+            "Longest title",
+            "Distribution"
+        ]
+    );
+}
+
+#[test]
+fn distribution_alone_is_fitted_on_the_lowercase_list() {
+    let dir = tempfile::tempdir().unwrap();
+    let dataset = dir.path().join("dataset.zip");
+    write_zip(
+        &dataset,
+        &[(
+            "a.json",
+            r#"[{"id": "1", "title": "T", "text": "a Bb bb ccc ccc CCC"}]"#,
+        )],
+    );
+    let output_path = dir.path().join("stats.txt");
+    let output = wiki_stats()
+        .arg("analyze")
+        .arg("--input")
+        .arg(&dataset)
+        .arg("--output")
+        .arg(&output_path)
+        .args(["--plain", "--sections", "distribution"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "analyze failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let text = fs::read_to_string(&output_path).unwrap();
+    assert!(
+        text.starts_with("\tDistribution\n\nZipf exponent: "),
+        "{}",
+        text
+    );
+    assert!(text.ends_with(
+        "Length 1: 2 occurrences, 2 distinct words\n\
+         Length 2: 2 occurrences, 1 distinct words\n\
+         Length 3: 3 occurrences, 1 distinct words\n"
+    ));
+}
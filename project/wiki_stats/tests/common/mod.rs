@@ -0,0 +1,34 @@
+// Each test crate only uses some of the helpers.
+#![allow(dead_code)]
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+// The program, unaffected by the configuration of the machine running the tests.
+pub fn wiki_stats() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_wiki_stats"));
+    for (name, _) in std::env::vars() {
+        if name.starts_with("WIKI_STATS_") {
+            command.env_remove(name);
+        }
+    }
+    command
+}
+
+// Entries whose name ends with '/' are directories.
+pub fn write_zip(path: &Path, entries: &[(&str, &str)]) {
+    let mut zip = ZipWriter::new(File::create(path).unwrap());
+    for (name, contents) in entries {
+        if name.ends_with('/') {
+            zip.add_directory(*name, FileOptions::default()).unwrap();
+        } else {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+    }
+    zip.finish().unwrap();
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use common::{wiki_stats, write_zip};
+use std::fs;
+
+#[test]
+fn config_applies_to_all_commands() {
+    let dir = tempfile::tempdir().unwrap();
+    write_zip(
+        &dir.path().join("dataset.zip"),
+        &[("a.json", r#"[{"id": "1", "title": "T", "text": "word"}]"#)],
+    );
+    fs::write(
+        dir.path().join("wiki_stats.toml"),
+        "quiet = true\nformat = \"tsv\"\nsections = [\"lower\"]\n",
+    )
+    .unwrap();
+    let analyze = wiki_stats()
+        .current_dir(dir.path())
+        .args(["analyze", "--input", "dataset.zip", "--output", "a.tsv"])
+        .output()
+        .unwrap();
+    assert!(analyze.status.success());
+    // 'merge' takes the output options too, & the global --quiet silences the logs.
+    let merge = wiki_stats()
+        .current_dir(dir.path())
+        .args(["merge", "a.tsv", "--output", "m.tsv"])
+        .output()
+        .unwrap();
+    assert!(merge.status.success());
+    assert!(
+        merge.stderr.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&merge.stderr)
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("m.tsv")).unwrap(),
+        "lower\tt\t1\nlower\tword\t1\n"
+    );
+}
+
+#[test]
+fn broken_config_fails_commands_but_not_completions() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("wiki_stats.toml"), "not = [valid").unwrap();
+    let merge = wiki_stats()
+        .current_dir(dir.path())
+        .args(["merge", "a.tsv"])
+        .output()
+        .unwrap();
+    assert!(!merge.status.success());
+    assert!(String::from_utf8_lossy(&merge.stderr).contains("Failed to parse config file"));
+    for command in [&["completions", "bash"][..], &["man"]] {
+        let output = wiki_stats()
+            .current_dir(dir.path())
+            .args(command)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?} failed", command);
+        assert!(!output.stdout.is_empty());
+    }
+}
+
+#[test]
+fn analyze_is_the_default_command() {
+    let dir = tempfile::tempdir().unwrap();
+    write_zip(
+        &dir.path().join("dataset.zip"),
+        &[("a.json", r#"[{"id": "1", "title": "T", "text": "word"}]"#)],
+    );
+    fs::write(
+        dir.path().join("wiki_stats.toml"),
+        "sections = [\"lower\"]\n",
+    )
+    .unwrap();
+    let output = wiki_stats()
+        .current_dir(dir.path())
+        .args(["-q", "-i", "dataset.zip", "-o", "-", "--format", "tsv"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "lower\tt\t1\nlower\tword\t1\n"
+    );
+    // Its options would be ignored by other commands, but the global ones are accepted.
+    let merge = wiki_stats()
+        .current_dir(dir.path())
+        .args(["-i", "dataset.zip", "merge", "a.tsv"])
+        .output()
+        .unwrap();
+    assert!(!merge.status.success());
+    assert!(String::from_utf8_lossy(&merge.stderr)
+        .contains("'--input <FILE>' is an option of 'analyze'"));
+    let merge = wiki_stats()
+        .current_dir(dir.path())
+        .args(["-q", "merge", "--help"])
+        .output()
+        .unwrap();
+    assert!(merge.status.success());
+}
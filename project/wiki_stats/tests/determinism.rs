@@ -0,0 +1,167 @@
+mod common;
+
+use common::{wiki_stats, write_zip};
+use std::fs;
+use std::path::Path;
+
+const WORDS: &[&str] = &[
+    "the",
+    "The",
+    "of",
+    "and",
+    "wiki",
+    "Wiki",
+    "article",
+    "river",
+    "city",
+    "stadt",
+    "und",
+    "der",
+    "die",
+    "ville",
+    "et",
+    "le",
+    "la",
+    "[[link]]",
+    "{{cite}}",
+    "naïve",
+    "Ünïcode",
+    "x",
+];
+
+// Small linear congruential generator, so that the dataset is the same on every run.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, bound: usize) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) as usize % bound
+    }
+}
+
+// Many small files, with articles (and titles) of equal length spread over several files, and
+// duplicates of earlier articles, so that ties & duplicates would be resolved differently if
+// they depended on which worker finishes first.
+fn write_dataset(path: &Path) {
+    let mut rng = Lcg(42);
+    let mut files = vec![];
+    let mut texts: Vec<String> = vec![];
+    for file in 0..24 {
+        let articles: Vec<serde_json::Value> = (0..20)
+            .map(|article| {
+                let text = if !texts.is_empty() && rng.next(5) == 0 {
+                    texts[rng.next(texts.len())].clone()
+                } else {
+                    let len = 30 + rng.next(4) * 10;
+                    (0..len)
+                        .map(|_| WORDS[rng.next(WORDS.len())])
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                };
+                texts.push(text.clone());
+                serde_json::json!({
+                    "id": format!("{}", article),
+                    "title": format!("T{:02}", rng.next(100)),
+                    "text": text,
+                })
+            })
+            .collect();
+        files.push((
+            format!("dir/{:02}.json", file),
+            serde_json::to_string(&articles).unwrap(),
+        ));
+    }
+    let entries: Vec<(&str, &str)> = files
+        .iter()
+        .map(|(name, contents)| (name.as_str(), contents.as_str()))
+        .collect();
+    write_zip(path, &entries);
+}
+
+fn analyze(dataset: &Path, output: &Path, threads: u32, options: &[&str]) -> Vec<u8> {
+    let status = wiki_stats()
+        .arg("analyze")
+        .arg("--input")
+        .arg(dataset)
+        .arg("--output")
+        .arg(output)
+        .arg("--threads")
+        .arg(threads.to_string())
+        .args(options)
+        .status()
+        .unwrap();
+    assert!(status.success(), "analyze {:?} failed", options);
+    fs::read(output).unwrap()
+}
+
+#[test]
+fn output_does_not_depend_on_threads() {
+    let dir = tempfile::tempdir().unwrap();
+    let dataset = dir.path().join("dataset.zip");
+    write_dataset(&dataset);
+    let targets = dir.path().join("targets.txt");
+    fs::write(&targets, "wiki\nriver\nx\n").unwrap();
+    let targets = targets.to_string_lossy();
+
+    let runs: &[(&str, &[&str])] = &[
+        ("json.txt", &["--format", "json"]),
+        ("stats.txt", &["--format", "plain"]),
+        ("stats.tsv", &["--format", "tsv"]),
+        ("stats.idx", &["--format", "index"]),
+        (
+            "full.txt",
+            &[
+                "--format",
+                "json",
+                "--languages",
+                "--char-stats",
+                "--strip-markup",
+                "wikitext",
+            ],
+        ),
+        (
+            "dedup.txt",
+            &["--format", "plain", "--dedup", "--dedup-threshold", "0.5"],
+        ),
+        (
+            "exclude.tsv",
+            &["--format", "tsv", "--dedup", "--dedup-exclude"],
+        ),
+        (
+            "spill.txt",
+            &["--format", "plain", "--spill", "--spill-threshold", "1K"],
+        ),
+        (
+            "cooccur.txt",
+            &["--format", "json", "--cooccur", &targets, "--window", "3"],
+        ),
+        // Fewer monitored words than distinct ones, so that Space-Saving evicts words.
+        (
+            "approx.txt",
+            &[
+                "--format",
+                "plain",
+                "--approx",
+                "--top-k",
+                "1",
+                "--memory-limit",
+                "8M",
+            ],
+        ),
+    ];
+    for (output, options) in runs {
+        let output = dir.path().join(output);
+        let expected = analyze(&dataset, &output, 1, options);
+        for threads in [1, 2, 3, 8] {
+            assert!(
+                analyze(&dataset, &output, threads, options) == expected,
+                "output of {:?} differs with {} threads",
+                options,
+                threads
+            );
+        }
+    }
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use common::wiki_stats;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Stdio};
+
+// Killed when the test ends, even if it fails.
+struct Served {
+    server: Child,
+    address: String,
+    _dir: tempfile::TempDir,
+}
+
+impl Drop for Served {
+    fn drop(&mut self) {
+        let _ = self.server.kill();
+        let _ = self.server.wait();
+    }
+}
+
+const STATS: &str = "\
+words\tthe\t5
+words\tThe\t3
+words\tnaïve\t2
+words\tcity\t2
+words\tx\t1
+lower\tthe\t8
+lower\tnaïve\t2
+lower\tcity\t2
+lower\tx\t1
+longest-article\tA long one\tdir/01.json/7\t1200
+longest-title\tThe longest title\tdir/02.json/3\t17
+";
+
+// Serves a TSV output on a port chosen by the system, read from the start message.
+fn serve() -> Served {
+    let dir = tempfile::tempdir().unwrap();
+    let stats = dir.path().join("stats.tsv");
+    fs::write(&stats, STATS).unwrap();
+    let mut server = wiki_stats()
+        .args(["serve", "--port", "0", "--stats"])
+        .arg(&stats)
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stderr = BufReader::new(server.stderr.take().unwrap());
+    let mut line = String::new();
+    stderr.read_line(&mut line).unwrap();
+    let address = line
+        .split("http://")
+        .nth(1)
+        .unwrap_or_else(|| panic!("unexpected start message: {:?}", line))
+        .trim()
+        .to_string();
+    Served {
+        server,
+        address,
+        _dir: dir,
+    }
+}
+
+// Minimal HTTP/1.0 client: the server closes the connection after the response.
+fn request(served: &Served, method: &str, path: &str) -> (u16, Value) {
+    let mut stream = TcpStream::connect(&served.address).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.0\r\nHost: {}\r\n\r\n",
+        method, path, served.address
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head
+        .to_lowercase()
+        .contains("content-type: application/json"));
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (status, serde_json::from_str(body).unwrap())
+}
+
+fn get(served: &Served, path: &str) -> (u16, Value) {
+    request(served, "GET", path)
+}
+
+#[test]
+fn word() {
+    let served = serve();
+    assert_eq!(
+        get(&served, "/word/The"),
+        (
+            200,
+            json!({ "word": "The", "appearances": 3, "lowercase_appearances": 8 })
+        )
+    );
+    assert_eq!(
+        get(&served, "/word/na%C3%AFve"),
+        (
+            200,
+            json!({ "word": "naïve", "appearances": 2, "lowercase_appearances": 2 })
+        )
+    );
+    // Only in the lowercase list.
+    assert_eq!(
+        get(&served, "/word/CITY"),
+        (
+            200,
+            json!({ "word": "CITY", "appearances": 0, "lowercase_appearances": 2 })
+        )
+    );
+    let (status, body) = get(&served, "/word/missing");
+    assert_eq!(status, 404);
+    assert!(body["error"].is_string());
+}
+
+#[test]
+fn top() {
+    let served = serve();
+    assert_eq!(
+        get(&served, "/top?n=3"),
+        (
+            200,
+            json!([
+                { "word": "the", "appearances": 5 },
+                { "word": "The", "appearances": 3 },
+                // Ties sorted by word.
+                { "word": "city", "appearances": 2 },
+            ])
+        )
+    );
+    assert_eq!(
+        get(&served, "/top?lowercase=true&n=1"),
+        (200, json!([{ "word": "the", "appearances": 8 }]))
+    );
+    let (status, body) = get(&served, "/top");
+    assert_eq!(status, 200);
+    assert_eq!(body.as_array().unwrap().len(), 5);
+    assert_eq!(get(&served, "/top?n=-1").0, 400);
+    assert_eq!(get(&served, "/top?lowercase=maybe").0, 400);
+}
+
+#[test]
+fn longest() {
+    let served = serve();
+    assert_eq!(
+        get(&served, "/longest"),
+        (
+            200,
+            json!({
+                "article": { "title": "A long one", "path": "dir/01.json/7", "size": 1200 },
+                "title": { "title": "The longest title", "path": "dir/02.json/3", "size": 17 },
+            })
+        )
+    );
+}
+
+#[test]
+fn errors() {
+    let served = serve();
+    assert_eq!(get(&served, "/unknown").0, 404);
+    assert_eq!(get(&served, "/word/").0, 404);
+    assert_eq!(get(&served, "/word/%ZZ").0, 400);
+    assert_eq!(request(&served, "POST", "/top").0, 405);
+}
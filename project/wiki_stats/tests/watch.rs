@@ -0,0 +1,120 @@
+mod common;
+
+use common::{wiki_stats, write_zip};
+use std::fs;
+use std::path::Path;
+
+fn watch_once(dir: &Path, output: &Path) {
+    let result = wiki_stats()
+        .arg("watch")
+        .arg(dir)
+        .arg("--output")
+        .arg(output)
+        .args(["--format", "tsv", "--sections", "lower", "--once"])
+        .output()
+        .unwrap();
+    assert!(
+        result.status.success(),
+        "watch failed: {}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+}
+
+#[test]
+fn malformed_archives_are_skipped_for_good() {
+    let dir = tempfile::tempdir().unwrap();
+    let archives = dir.path().join("archives");
+    fs::create_dir(&archives).unwrap();
+    let output = dir.path().join("stats.tsv");
+    write_zip(
+        &archives.join("1.zip"),
+        &[("a.json", r#"[{"id": "1", "title": "One", "text": "word"}]"#)],
+    );
+    write_zip(
+        &archives.join("2.zip"),
+        &[("b.json", r#"[{"id": "2", "title": "Bad", "text": "#)],
+    );
+    watch_once(&archives, &output);
+    assert_eq!(
+        fs::read_to_string(&output).unwrap(),
+        "lower\tone\t1\nlower\tword\t1\n"
+    );
+    let state: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(dir.path().join("stats.tsv.watch.json")).unwrap())
+            .unwrap();
+    assert_eq!(
+        state,
+        serde_json::json!({ "archives": ["1.zip"], "failed": ["2.zip"], "staged": false })
+    );
+
+    // After a restart, only the new archive is analyzed & merged.
+    write_zip(
+        &archives.join("3.zip"),
+        &[(
+            "c.json",
+            r#"[{"id": "3", "title": "Word", "text": "three"}]"#,
+        )],
+    );
+    watch_once(&archives, &output);
+    assert_eq!(
+        fs::read_to_string(&output).unwrap(),
+        "lower\tone\t1\nlower\tthree\t1\nlower\tword\t2\n"
+    );
+}
+
+#[test]
+fn staged_outputs_are_moved_into_place_only_if_committed() {
+    let dir = tempfile::tempdir().unwrap();
+    let archives = dir.path().join("archives");
+    fs::create_dir(&archives).unwrap();
+    let output = dir.path().join("stats.tsv");
+    let staged = dir.path().join("stats.tsv.watch.new");
+    let state = dir.path().join("stats.tsv.watch.json");
+    write_zip(
+        &archives.join("1.zip"),
+        &[("a.json", r#"[{"id": "1", "title": "One", "text": "word"}]"#)],
+    );
+    watch_once(&archives, &output);
+    assert!(!staged.exists());
+
+    // A crash after the output was staged, but before the state listing its archive was saved.
+    fs::write(&staged, "lower\tuncommitted\t1\n").unwrap();
+    watch_once(&archives, &output);
+    assert!(!staged.exists());
+    assert_eq!(
+        fs::read_to_string(&output).unwrap(),
+        "lower\tone\t1\nlower\tword\t1\n"
+    );
+
+    // A crash after the state was saved, but before the staged output was moved into place.
+    fs::write(&staged, "lower\tcommitted\t1\n").unwrap();
+    fs::write(
+        &state,
+        r#"{ "archives": ["1.zip", "2.zip"], "failed": [], "staged": true }"#,
+    )
+    .unwrap();
+    watch_once(&archives, &output);
+    assert!(!staged.exists());
+    assert_eq!(
+        fs::read_to_string(&output).unwrap(),
+        "lower\tcommitted\t1\n"
+    );
+    let state: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&state).unwrap()).unwrap();
+    assert_eq!(state["staged"], false);
+}
+
+#[test]
+fn languages_need_a_format_keeping_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let result = wiki_stats()
+        .arg("watch")
+        .arg(dir.path())
+        .arg("--output")
+        .arg(dir.path().join("stats.tsv"))
+        .args(["--format", "tsv", "--languages", "--once"])
+        .output()
+        .unwrap();
+    assert!(!result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr).contains("don't keep the languages"));
+}